@@ -0,0 +1,347 @@
+//! Command-line interface.
+
+use crate::config::inputs;
+use crate::{identity, metrics, report, state};
+use clap::{Arg, crate_authors, crate_description, crate_name, crate_version};
+use failure::{bail, ResultExt};
+use log::{debug, info, trace, warn, LevelFilter};
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::Path;
+use std::time::Duration;
+
+/// Parse the reporting.enabled and collecting.level keys from config fragments,
+/// and check that the keys are set to a valid telemetry setting. If not,
+/// or in case of other error, return non-zero.
+fn check_config(config: &inputs::ConfigInput) -> failure::Fallible<()> {
+    if config.reporting.enabled.unwrap() {
+        println!("Reporting enabled.");
+        if config.reporting.endpoint.is_none() && config.reporting.statsd_host.is_none() {
+            println!("No reporting endpoint configured, nothing will be reported.");
+        }
+
+        let collecting_level = &config.collecting.level;
+        match collecting_level.as_str() {
+            "none" | "off" | "minimal" | "full" => println!("Collection set at level '{}'.", collecting_level),
+            _ if config.collecting.strict_level => bail!("invalid collection level '{}'", collecting_level),
+            _ => println!("Unknown collection level '{}', collecting at level 'minimal'.", collecting_level),
+        }
+    } else {
+        println!("Reporting disabled.");
+    }
+
+    Ok(())
+}
+
+/// Run the command-line tool.
+pub fn run() -> failure::Fallible<()> {
+    let matches = clap::app_from_crate!()
+        .arg(Arg::with_name("v")
+            .short("v")
+            .multiple(true)
+            .help("Sets log verbosity level"))
+        .arg(Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Prints the report instead of sending it"))
+        .arg(Arg::with_name("fixtures-dir")
+            .long("fixtures-dir")
+            .value_name("DIR")
+            .requires("dry-run")
+            .help("Collects data from files in DIR instead of the live system"))
+        .arg(Arg::with_name("root")
+            .long("root")
+            .value_name("DIR")
+            .conflicts_with("fixtures-dir")
+            .help("Collects data from the filesystem mounted at DIR instead of /"))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .value_name("FILE")
+            .conflicts_with("dry-run")
+            .help("Writes the report (or metrics) to FILE instead of sending it"))
+        .arg(Arg::with_name("metrics")
+            .long("metrics")
+            .help("Prints the collected data as Prometheus metrics instead of reporting it"))
+        .arg(Arg::with_name("metrics-listen")
+            .long("metrics-listen")
+            .value_name("ADDR")
+            .requires("metrics")
+            .conflicts_with("output")
+            .help("Serves the metrics over HTTP on ADDR (e.g. 127.0.0.1:9101) for scraping"))
+        .get_matches();
+
+    let log_level = match matches.occurrences_of("v") {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    env_logger::Builder::from_default_env()
+        .default_format_timestamp(false)
+        .default_format_module_path(false)
+        .filter(None, log_level)
+        .try_init()?;
+
+    let dirs = vec![
+        String::from("/usr/lib"),
+        String::from("/run"),
+        String::from("/etc"),
+    ];
+    let mut config = inputs::ConfigInput::read_configs(dirs, crate_name!())
+        .context("failed to read configuration input")?;
+    if matches.is_present("dry-run") {
+        config.reporting.dry_run = true;
+    }
+
+    check_config(&config)?;
+
+    if config.reporting.enabled.unwrap() {
+        let collect = || -> failure::Fallible<identity::Identity> {
+            let id = match (matches.value_of("fixtures-dir"), matches.value_of("root")) {
+                (Some(dir), _) => {
+                    identity::Identity::from_fixtures(&config.collecting, Path::new(dir))
+                        .context(format!("failed to build identity from '{}'", dir))?
+                }
+                (None, Some(root)) => {
+                    let paths =
+                        identity::Paths::from_config(&config.collecting).with_root(Path::new(root));
+                    identity::Identity::with_paths(&config.collecting, &paths)
+                        .context("failed to build identity")?
+                }
+                (None, None) => identity::Identity::new(&config.collecting)
+                    .context("failed to build identity")?,
+            };
+            Ok(id)
+        };
+        let mut id = collect()?;
+        debug!("collected data: {:?}", id.get_data());
+        trace!("JSON payload: {}", id.to_json()?);
+
+        if let Some(addr) = matches.value_of("metrics-listen") {
+            let listener =
+                TcpListener::bind(addr).context(format!("failed to listen on '{}'", addr))?;
+            info!("serving metrics on '{}'", addr);
+            // Collect again on each scrape, so that metrics are up-to-date.
+            metrics::serve(&listener, || Ok(metrics::render(&collect()?)))?;
+        } else if matches.is_present("metrics") {
+            match matches.value_of("output") {
+                Some(output) => metrics::write_textfile(&id, Path::new(output))
+                    .context(format!("failed to write metrics to '{}'", output))?,
+                None => print!("{}", metrics::render(&id)),
+            }
+        } else if id.level == identity::CollectingLevel::None {
+            info!("collecting level is 'none', not reporting");
+        } else if let Some(output) = matches.value_of("output") {
+            report::write_to_file(Path::new(output), &id)
+                .context(format!("failed to write report to '{}'", output))?;
+        } else {
+            report_identity(&mut id, &config.reporting, &mut std::io::stdout())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Send the collected identity to the reporting endpoint, numbering it with
+/// the next report sequence, or in dry-run mode only print it to `out`.
+///
+/// Nothing is sent, printed or recorded at the `"none"` level.
+fn report_identity(
+    id: &mut identity::Identity,
+    cfg: &inputs::ReportingInput,
+    out: &mut impl Write,
+) -> failure::Fallible<()> {
+    if id.level == identity::CollectingLevel::None {
+        info!("collecting level is 'none', not reporting");
+        return Ok(());
+    }
+
+    if cfg.dry_run {
+        writeln!(out, "{}", id.to_json_pretty()?)?;
+        return Ok(());
+    }
+
+    // Without an endpoint there is nowhere to report to, as in the default
+    // configuration: nothing is sent nor recorded.
+    if cfg.statsd_host.is_none() && cfg.endpoint.is_none() {
+        warn!("no reporting endpoint configured (`reporting.endpoint`), not reporting");
+        return Ok(());
+    }
+
+    let state_path = Path::new(&cfg.state_file);
+    if cfg.send_once && !state::should_report(id, state_path) {
+        info!("OS version already reported, skipping");
+        return Ok(());
+    }
+
+    // Only reports actually sent are numbered.
+    match state::next_report_sequence(Path::new(&cfg.sequence_file)) {
+        Ok(sequence) => id.report_sequence = Some(sequence),
+        Err(e) => warn!("failed to update report sequence: {}", e),
+    }
+
+    let jitter = report::startup_jitter(cfg);
+    if jitter > Duration::from_secs(0) {
+        info!("waiting {:.1}s before reporting", jitter.as_secs_f64());
+        std::thread::sleep(jitter);
+    }
+
+    if let Some(host) = &cfg.statsd_host {
+        report::statsd::send_statsd(id, host).context("failed to report identity")?;
+    } else if let Some(endpoint) = &cfg.endpoint {
+        let reporter = report::Reporter::new(cfg)?;
+        if let Err(e) = reporter.flush_spool(endpoint) {
+            warn!("failed to send spooled reports: {}", e);
+        }
+        reporter
+            .submit(endpoint, id)
+            .context("failed to report identity")?;
+    }
+
+    if cfg.send_once {
+        if let Err(e) = state::record_report(id, state_path) {
+            warn!("failed to record report: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_config_level() {
+        let mut config = inputs::ConfigInput {
+            collecting: inputs::CollectingInput {
+                level: "fulll".to_string(),
+                ..inputs::CollectingInput::default()
+            },
+            reporting: inputs::ReportingInput::mock(),
+        };
+        check_config(&config).unwrap();
+
+        config.collecting.strict_level = true;
+        check_config(&config).unwrap_err();
+    }
+
+    #[test]
+    fn test_report_identity() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/").with_status(200).expect(1).create();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut id = identity::Identity::mock_default("minimal");
+        let cfg = inputs::ReportingInput {
+            endpoint: Some(server.url()),
+            sequence_file: dir.path().join("seq").to_str().unwrap().to_string(),
+            ..inputs::ReportingInput::mock()
+        };
+        let mut out = vec![];
+        report_identity(&mut id, &cfg, &mut out).unwrap();
+        mock.assert();
+        assert!(out.is_empty());
+        assert_eq!(id.report_sequence, Some(0));
+    }
+
+    #[test]
+    fn test_report_identity_none() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/").expect(0).create();
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = inputs::ReportingInput {
+            endpoint: Some(server.url()),
+            send_once: true,
+            state_file: dir.path().join("state.json").to_str().unwrap().to_string(),
+            sequence_file: dir.path().join("seq").to_str().unwrap().to_string(),
+            ..inputs::ReportingInput::mock()
+        };
+
+        let level = inputs::CollectingInput {
+            level: "off".to_string(),
+            ..inputs::CollectingInput::default()
+        };
+        let mut id = identity::Identity::with_paths(&level, &identity::Paths::default()).unwrap();
+        let mut out = vec![];
+        report_identity(&mut id, &cfg, &mut out).unwrap();
+        mock.assert();
+        assert!(out.is_empty());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_report_identity_no_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = inputs::ReportingInput {
+            send_once: true,
+            state_file: dir.path().join("state.json").to_str().unwrap().to_string(),
+            sequence_file: dir.path().join("seq").to_str().unwrap().to_string(),
+            ..inputs::ReportingInput::mock()
+        };
+        let mut id = identity::Identity::mock_default("minimal");
+        let mut out = vec![];
+        report_identity(&mut id, &cfg, &mut out).unwrap();
+        assert!(out.is_empty());
+        assert_eq!(id.report_sequence, None);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        // Dry runs need no endpoint.
+        let cfg = inputs::ReportingInput {
+            dry_run: true,
+            ..cfg
+        };
+        report_identity(&mut id, &cfg, &mut out).unwrap();
+        assert!(!out.is_empty());
+    }
+
+    #[test]
+    fn test_report_identity_send_once() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/").with_status(200).expect(2).create();
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = inputs::ReportingInput {
+            send_once: true,
+            state_file: dir.path().join("state.json").to_str().unwrap().to_string(),
+            sequence_file: dir.path().join("seq").to_str().unwrap().to_string(),
+            endpoint: Some(server.url()),
+            ..inputs::ReportingInput::mock()
+        };
+
+        // The same OS version is only reported once.
+        let mut id = identity::Identity::mock_default("minimal");
+        report_identity(&mut id, &cfg, &mut vec![]).unwrap();
+        report_identity(&mut id, &cfg, &mut vec![]).unwrap();
+
+        let mut updated = identity::Identity {
+            current_os_version: Some("31.20191217.2.0".to_string()),
+            ..identity::Identity::mock_default("minimal")
+        };
+        report_identity(&mut updated, &cfg, &mut vec![]).unwrap();
+        mock.assert();
+
+        // Skipped reports are not numbered.
+        let sequence = std::fs::read_to_string(&cfg.sequence_file).unwrap();
+        assert_eq!(sequence, "2\n");
+    }
+
+    #[test]
+    fn test_report_identity_dry_run() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/").expect(0).create();
+
+        let mut id = identity::Identity::mock_default("full");
+        let cfg = inputs::ReportingInput {
+            endpoint: Some(server.url()),
+            dry_run: true,
+            ..inputs::ReportingInput::mock()
+        };
+        let mut out = vec![];
+        report_identity(&mut id, &cfg, &mut out).unwrap();
+        mock.assert();
+
+        let printed = String::from_utf8(out).unwrap();
+        assert!(printed.contains("\n  \"level\": \"full\""), "{}", printed);
+        let value: serde_json::Value = serde_json::from_str(&printed).unwrap();
+        assert_eq!(value, id.to_json_value().unwrap());
+    }
+}
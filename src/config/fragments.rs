@@ -3,24 +3,83 @@
 use serde::Deserialize;
 
 /// Pinger config.
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Default, Deserialize, PartialEq)]
 pub(crate) struct ConfigFragment {
     pub(crate) collecting: Option<CollectingFragment>,
     pub(crate) reporting: Option<ReportingFragment>,
 }
 
 /// Collecting config group.
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Default, Deserialize, PartialEq)]
 pub(crate) struct CollectingFragment {
-    /// Collection level, may be `"minimal"` or `"full"` (default: "minimal").
+    /// Collection level, may be `"none"`, `"minimal"` or `"full"` (default: "minimal").
     pub(crate) level: Option<String>,
+    /// Timeout in seconds for rpm-ostree invocations (default: 30).
+    pub(crate) rpm_ostree_timeout_secs: Option<u64>,
+    /// Kernel command-line arguments to report at the `"full"` level (default: none).
+    pub(crate) kernel_args: Option<Vec<String>>,
+    /// Path to the kernel command-line (default: "/proc/cmdline").
+    pub(crate) kernel_args_path: Option<String>,
+    /// Path to the aleph version file (default: "/.coreos-aleph-version.json").
+    pub(crate) aleph_version_path: Option<String>,
+    /// Path to the Afterburn metadata file (default: "/run/metadata/afterburn").
+    pub(crate) afterburn_metadata_path: Option<String>,
+    /// Data keys to report, further restricting the level; empty means all (default: all).
+    pub(crate) report_fields: Option<Vec<String>>,
+    /// Data keys not to report, taking precedence over `report_fields` (default: none).
+    pub(crate) exclude_fields: Option<Vec<String>>,
+    /// Fail on an unknown level instead of collecting at `"minimal"` (default: false).
+    pub(crate) strict_level: Option<bool>,
+    /// Granularity of the reported instance type, may be `"full"`, `"family"`
+    /// or `"none"` (default: "full").
+    pub(crate) instance_type_granularity: Option<String>,
 }
 
 /// Reporting config group.
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Default, Deserialize, PartialEq)]
 pub(crate) struct ReportingFragment {
     /// Reporting enablement flag (required).
     pub(crate) enabled: Option<bool>,
+    /// URL of the reporting endpoint, must use https (default: none, nothing is sent).
+    pub(crate) endpoint: Option<String>,
+    /// Timeout in seconds for each report request (default: 30).
+    pub(crate) timeout_secs: Option<u64>,
+    /// Maximum number of retries on transient report failures (default: 4).
+    pub(crate) max_retries: Option<u32>,
+    /// Delay in seconds before the first retry, doubled on each further retry (default: 1).
+    pub(crate) base_backoff_secs: Option<u64>,
+    /// Maximum delay in seconds honored from a `Retry-After` header (default: 3600).
+    pub(crate) max_retry_after_secs: Option<u64>,
+    /// URL of the proxy to send reports through (default: from environment).
+    pub(crate) proxy: Option<String>,
+    /// `host:port` of a statsd server to send metrics to, instead of
+    /// reporting to the endpoint (default: none).
+    pub(crate) statsd_host: Option<String>,
+    /// Directory where reports which could not be sent are spooled
+    /// (default: "/var/lib/fedora-coreos-pinger/spool").
+    pub(crate) spool_dir: Option<String>,
+    /// Maximum number of spooled reports, 0 disables spooling (default: 10).
+    pub(crate) spool_max_files: Option<usize>,
+    /// Print reports instead of sending them (default: false).
+    pub(crate) dry_run: Option<bool>,
+    /// Only report again once the OS version changed (default: false).
+    pub(crate) send_once: Option<bool>,
+    /// File recording the last successful report
+    /// (default: "/var/lib/fedora-coreos-pinger/state.json").
+    pub(crate) state_file: Option<String>,
+    /// File holding the sequence number of the next report
+    /// (default: "/var/lib/fedora-coreos-pinger/seq").
+    pub(crate) sequence_file: Option<String>,
+    /// Maximum random delay in seconds before reporting (default: 60).
+    pub(crate) max_jitter_secs: Option<u64>,
+    /// Seed making the random delay reproducible (default: none).
+    pub(crate) jitter_seed: Option<u64>,
+    /// Serialization format of reports, may be `"json"` or `"msgpack"` (default: "json").
+    pub(crate) format: Option<String>,
+    /// Whether to gzip-compress large reports (default: true).
+    pub(crate) compress: Option<bool>,
+    /// Size in bytes above which reports are gzip-compressed (default: 512).
+    pub(crate) gzip_threshold_bytes: Option<usize>,
 }
 
 #[cfg(test)]
@@ -39,9 +98,11 @@ mod tests {
         let expected = ConfigFragment {
             collecting: Some(CollectingFragment {
                 level: Some("minimal".to_string()),
+                ..Default::default()
             }),
             reporting: Some(ReportingFragment {
                 enabled: Some(true),
+                ..Default::default()
             }),
         };
 
@@ -1,11 +1,28 @@
 //! Configuration input (reading snippets from filesystem and merging).
-/// Modified source from zincati: https://github.com/coreos/zincati/blob/60f3a9144b34ebfa7f7a0fe98f8d641a760ee8f0/src/config/inputs.rs.
+//! Modified source from zincati: https://github.com/coreos/zincati/blob/60f3a9144b34ebfa7f7a0fe98f8d641a760ee8f0/src/config/inputs.rs.
 
 use crate::config::fragments;
+use crate::identity;
+use crate::state;
 
 use failure::{bail, ResultExt};
+use log::warn;
 use serde::Serialize;
 use std::{collections, path};
+use thiserror::Error;
+
+/// Valid collecting levels (`"off"` is accepted as an alias of `"none"`).
+const COLLECTING_LEVELS: &[&str] = &["none", "minimal", "full"];
+
+/// Error returned when `collecting.level` is not a valid collecting level.
+#[derive(Debug, Error)]
+#[error(
+    "invalid collecting level '{level}', expected one of: {}",
+    COLLECTING_LEVELS.join(", ")
+)]
+pub struct InvalidLevelError {
+    pub(crate) level: String,
+}
 
 #[derive(Debug, Serialize)]
 pub(crate) struct ConfigInput {
@@ -51,8 +68,8 @@ impl ConfigInput {
             bufrd
                 .read_to_end(&mut content)
                 .context(format!("failed to read content of '{}'", path.display()))?;
-            let config: fragments::ConfigFragment =
-                toml::from_slice(&content).context("failed to parse TOML")?;
+            let config: fragments::ConfigFragment = toml::from_slice(&content)
+                .context(format!("failed to parse TOML in '{}'", path.display()))?;
 
             if let Some(c) = config.collecting {
                 collecting_configs.push(c);
@@ -73,31 +90,134 @@ impl ConfigInput {
     fn validate_input(
         &self
     ) -> failure::Fallible<()> {
-        if self.reporting.enabled == None {
+        if self.reporting.enabled.is_none() {
             bail!("Required configuration key `reporting.enabled` not specified.");
         }
 
+        // Unknown levels fall back to "minimal" when collecting, unless strict.
+        let level = &self.collecting.level;
+        if identity::CollectingLevel::parse(level).is_none() && self.collecting.strict_level {
+            return Err(InvalidLevelError {
+                level: level.clone(),
+            }
+            .into());
+        }
+
+        let granularity = &self.collecting.instance_type_granularity;
+        if identity::InstanceTypeGranularity::parse(granularity).is_none() {
+            bail!(
+                "invalid instance type granularity '{}', expected one of: full, family, none",
+                granularity
+            );
+        }
+
+        let known = identity::field_names();
+        let report_fields = self.collecting.report_fields.iter().flatten();
+        if let Some(field) = report_fields.into_iter().find(|f| !known.contains(f)) {
+            bail!("unknown field '{}' in `collecting.report_fields`", field);
+        }
+        let exclude_fields = self.collecting.exclude_fields.iter().flatten();
+        for field in exclude_fields.filter(|f| !known.contains(f)) {
+            warn!("unknown field '{}' in `collecting.exclude_fields`", field);
+        }
+
+        let format = &self.reporting.format;
+        if identity::Format::parse(format).is_none() {
+            bail!(
+                "invalid report format '{}', expected one of: json, msgpack",
+                format
+            );
+        }
+
+        // The endpoint is not used, hence not checked, with reporting disabled.
+        let reporting = self.reporting.enabled == Some(true);
+        if let Some(endpoint) = self.reporting.endpoint.as_ref().filter(|_| reporting) {
+            let url = reqwest::Url::parse(endpoint)
+                .context(format!("invalid reporting endpoint '{}'", endpoint))?;
+            if url.scheme() != "https" {
+                bail!("reporting endpoint '{}' must use https", endpoint);
+            }
+        }
+
         Ok(())
     }
 }
 
+/// Input config for the collecting group.
 #[derive(Clone, Debug, Serialize)]
-pub(crate) struct CollectingInput {
-    pub(crate) level: String,
+pub struct CollectingInput {
+    pub level: String,
+    pub rpm_ostree_timeout_secs: u64,
+    pub kernel_args: Vec<String>,
+    pub kernel_args_path: String,
+    pub aleph_version_path: String,
+    pub afterburn_metadata_path: String,
+    pub report_fields: Option<Vec<String>>,
+    pub exclude_fields: Option<Vec<String>>,
+    pub strict_level: bool,
+    pub instance_type_granularity: String,
+}
+
+impl Default for CollectingInput {
+    fn default() -> Self {
+        Self {
+            // Default collecting level is `"minimal"`.
+            level: String::from("minimal"),
+            // Default rpm-ostree timeout is 30 seconds.
+            rpm_ostree_timeout_secs: 30,
+            // Default is to report no kernel arguments.
+            kernel_args: vec![],
+            kernel_args_path: String::from(identity::KERNEL_ARGS_FILE),
+            aleph_version_path: String::from(identity::OS_ALEPH_VERSION_FILE),
+            afterburn_metadata_path: String::from(identity::AFTERBURN_METADATA),
+            // Default is to report all fields collected at the configured level.
+            report_fields: None,
+            // Default is to exclude no field.
+            exclude_fields: None,
+            // Default is to collect at the `"minimal"` level if unknown.
+            strict_level: false,
+            // Default is to report the instance type as collected.
+            instance_type_granularity: String::from("full"),
+        }
+    }
 }
 
 impl CollectingInput {
     /// Convert fragments into input config for collecting group.
     fn from_fragments(fragments: Vec<fragments::CollectingFragment>) -> Self {
-        let mut cfg = Self {
-            // Default collecting level is `"minimal"`.
-            level: String::from("minimal"),
-        };
+        let mut cfg = Self::default();
 
         for snip in fragments {
             if let Some(l) = snip.level {
                 cfg.level = l;
             }
+            if let Some(t) = snip.rpm_ostree_timeout_secs {
+                cfg.rpm_ostree_timeout_secs = t;
+            }
+            if let Some(k) = snip.kernel_args {
+                cfg.kernel_args = k;
+            }
+            if let Some(p) = snip.kernel_args_path {
+                cfg.kernel_args_path = p;
+            }
+            if let Some(p) = snip.aleph_version_path {
+                cfg.aleph_version_path = p;
+            }
+            if let Some(p) = snip.afterburn_metadata_path {
+                cfg.afterburn_metadata_path = p;
+            }
+            if let Some(f) = snip.report_fields {
+                cfg.report_fields = Some(f);
+            }
+            if let Some(f) = snip.exclude_fields {
+                cfg.exclude_fields = Some(f);
+            }
+            if let Some(s) = snip.strict_level {
+                cfg.strict_level = s;
+            }
+            if let Some(g) = snip.instance_type_granularity {
+                cfg.instance_type_granularity = g;
+            }
         }
 
         cfg
@@ -107,21 +227,351 @@ impl CollectingInput {
 #[derive(Debug, Serialize)]
 pub(crate) struct ReportingInput {
     pub(crate) enabled: Option<bool>,
+    pub(crate) endpoint: Option<String>,
+    pub(crate) timeout_secs: u64,
+    pub(crate) max_retries: u32,
+    pub(crate) base_backoff_secs: u64,
+    pub(crate) max_retry_after_secs: u64,
+    pub(crate) proxy: Option<String>,
+    pub(crate) statsd_host: Option<String>,
+    pub(crate) spool_dir: String,
+    pub(crate) spool_max_files: usize,
+    pub(crate) dry_run: bool,
+    pub(crate) send_once: bool,
+    pub(crate) state_file: String,
+    pub(crate) sequence_file: String,
+    pub(crate) max_jitter_secs: u64,
+    pub(crate) jitter_seed: Option<u64>,
+    pub(crate) format: String,
+    pub(crate) compress: bool,
+    pub(crate) gzip_threshold_bytes: usize,
+}
+
+impl Default for ReportingInput {
+    fn default() -> Self {
+        Self {
+            enabled: None,
+            // Default is to send nothing until an endpoint is configured.
+            endpoint: None,
+            // Default report request timeout is 30 seconds.
+            timeout_secs: 30,
+            // Default is 4 retries (5 attempts), starting with a 1 second delay.
+            max_retries: 4,
+            base_backoff_secs: 1,
+            // Default is to wait up to an hour when asked to retry later.
+            max_retry_after_secs: 3600,
+            // Default is to use the proxy from environment, if any.
+            proxy: None,
+            // Default is to report to the endpoint.
+            statsd_host: None,
+            // Default is to spool up to 10 reports which could not be sent.
+            spool_dir: String::from("/var/lib/fedora-coreos-pinger/spool"),
+            spool_max_files: 10,
+            dry_run: false,
+            // Default is to report on every run.
+            send_once: false,
+            state_file: String::from(state::DEFAULT_STATE_FILE),
+            sequence_file: String::from(state::DEFAULT_SEQUENCE_FILE),
+            // Default is to wait up to a minute, with a random seed.
+            max_jitter_secs: 60,
+            jitter_seed: None,
+            // Default is to send JSON reports.
+            format: String::from("json"),
+            // Default is to compress reports larger than 512 bytes.
+            compress: true,
+            gzip_threshold_bytes: 512,
+        }
+    }
 }
 
 impl ReportingInput {
     /// Convert fragments into input config for reporting group.
     fn from_fragments(fragments: Vec<fragments::ReportingFragment>) -> Self {
-        let mut cfg = Self {
-            enabled: None,
-        };
+        let mut cfg = Self::default();
 
         for snip in fragments {
-            /* Option is directly passed so that the setting being given
-             * explicitly can later be validated. */
-            cfg.enabled = snip.enabled;
+            /* Option is kept so that the setting being given explicitly
+             * can later be validated. */
+            if let Some(e) = snip.enabled {
+                cfg.enabled = Some(e);
+            }
+            if let Some(e) = snip.endpoint {
+                cfg.endpoint = Some(e);
+            }
+            if let Some(t) = snip.timeout_secs {
+                cfg.timeout_secs = t;
+            }
+            if let Some(r) = snip.max_retries {
+                cfg.max_retries = r;
+            }
+            if let Some(b) = snip.base_backoff_secs {
+                cfg.base_backoff_secs = b;
+            }
+            if let Some(m) = snip.max_retry_after_secs {
+                cfg.max_retry_after_secs = m;
+            }
+            if let Some(p) = snip.proxy {
+                cfg.proxy = Some(p);
+            }
+            if let Some(h) = snip.statsd_host {
+                cfg.statsd_host = Some(h);
+            }
+            if let Some(d) = snip.spool_dir {
+                cfg.spool_dir = d;
+            }
+            if let Some(m) = snip.spool_max_files {
+                cfg.spool_max_files = m;
+            }
+            if let Some(d) = snip.dry_run {
+                cfg.dry_run = d;
+            }
+            if let Some(s) = snip.send_once {
+                cfg.send_once = s;
+            }
+            if let Some(f) = snip.state_file {
+                cfg.state_file = f;
+            }
+            if let Some(f) = snip.sequence_file {
+                cfg.sequence_file = f;
+            }
+            if let Some(j) = snip.max_jitter_secs {
+                cfg.max_jitter_secs = j;
+            }
+            if let Some(s) = snip.jitter_seed {
+                cfg.jitter_seed = Some(s);
+            }
+            if let Some(f) = snip.format {
+                cfg.format = f;
+            }
+            if let Some(c) = snip.compress {
+                cfg.compress = c;
+            }
+            if let Some(g) = snip.gzip_threshold_bytes {
+                cfg.gzip_threshold_bytes = g;
+            }
         }
 
         cfg
     }
+
+    /// Return a config reporting to nothing, without waiting, retrying,
+    /// spooling or compressing, for tests to adjust.
+    #[cfg(test)]
+    pub(crate) fn mock() -> Self {
+        Self {
+            enabled: Some(true),
+            timeout_secs: 5,
+            max_retries: 0,
+            base_backoff_secs: 0,
+            max_retry_after_secs: 0,
+            spool_dir: "/nonexistent/spool".to_string(),
+            spool_max_files: 0,
+            state_file: "/nonexistent/state.json".to_string(),
+            sequence_file: "/nonexistent/seq".to_string(),
+            max_jitter_secs: 0,
+            gzip_threshold_bytes: usize::MAX,
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_endpoint(endpoint: Option<&str>) -> ConfigInput {
+        let reporting = fragments::ReportingFragment {
+            enabled: Some(true),
+            endpoint: endpoint.map(String::from),
+            ..Default::default()
+        };
+        ConfigInput {
+            collecting: CollectingInput::from_fragments(vec![]),
+            reporting: ReportingInput::from_fragments(vec![reporting]),
+        }
+    }
+
+    /// Write config fragments into `<root>/<dir>/pinger/config.d/` and return
+    /// the list of scanned directories.
+    fn write_fragments(root: &path::Path, fragments: &[(&str, &str, &str)]) -> Vec<String> {
+        for (dir, name, content) in fragments {
+            let confd = root.join(dir).join("pinger/config.d");
+            std::fs::create_dir_all(&confd).unwrap();
+            std::fs::write(confd.join(name), content).unwrap();
+        }
+        vec!["usr/lib", "run", "etc"]
+            .into_iter()
+            .map(|dir| root.join(dir).to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_configs_merge() {
+        let root = tempfile::tempdir().unwrap();
+        let dirs = write_fragments(
+            root.path(),
+            &[
+                (
+                    "usr/lib",
+                    "10-base.toml",
+                    concat!(
+                        "[collecting]\nlevel = \"minimal\"\n\n",
+                        "[reporting]\nenabled = true\n",
+                        "endpoint = \"https://base.example.com/report\"\n",
+                    ),
+                ),
+                ("etc", "20-level.toml", "[collecting]\nlevel = \"none\"\n"),
+                (
+                    "run",
+                    "30-override.toml",
+                    "[collecting]\nlevel = \"full\"\n\n[reporting]\ntimeout_secs = 10\n",
+                ),
+            ],
+        );
+        let cfg = ConfigInput::read_configs(dirs, "pinger").unwrap();
+
+        // Later fragments override earlier ones, field by field.
+        assert_eq!(cfg.collecting.level, "full");
+        assert_eq!(cfg.reporting.enabled, Some(true));
+        assert_eq!(
+            cfg.reporting.endpoint,
+            Some("https://base.example.com/report".to_string())
+        );
+        assert_eq!(cfg.reporting.timeout_secs, 10);
+    }
+
+    #[test]
+    fn test_read_configs_malformed() {
+        let root = tempfile::tempdir().unwrap();
+        let dirs = write_fragments(
+            root.path(),
+            &[
+                ("usr/lib", "10-base.toml", "[reporting]\nenabled = true\n"),
+                ("etc", "20-broken.toml", "[collecting]\nlevel = \n"),
+            ],
+        );
+        let err = ConfigInput::read_configs(dirs, "pinger").unwrap_err();
+        assert!(
+            err.to_string().contains("20-broken.toml"),
+            "unexpected error: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_collecting_level() {
+        for level in &["none", "off", "minimal", "full"] {
+            let mut cfg = with_endpoint(None);
+            cfg.collecting.level = level.to_string();
+            cfg.validate_input().unwrap();
+        }
+
+        let mut cfg = with_endpoint(None);
+        cfg.collecting.level = "fulll".to_string();
+        cfg.validate_input().unwrap();
+
+        cfg.collecting.strict_level = true;
+        let err = cfg.validate_input().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<InvalidLevelError>().unwrap().level,
+            "fulll"
+        );
+        assert_eq!(
+            err.to_string(),
+            "invalid collecting level 'fulll', expected one of: none, minimal, full"
+        );
+    }
+
+    #[test]
+    fn test_instance_type_granularity() {
+        let mut cfg = with_endpoint(None);
+        assert_eq!(cfg.collecting.instance_type_granularity, "full");
+        for granularity in &["full", "family", "none"] {
+            cfg.collecting.instance_type_granularity = granularity.to_string();
+            cfg.validate_input().unwrap();
+        }
+
+        cfg.collecting.instance_type_granularity = "coarse".to_string();
+        let err = cfg.validate_input().unwrap_err();
+        assert!(err.to_string().contains("'coarse'"), "{}", err);
+    }
+
+    #[test]
+    fn test_report_format() {
+        let mut cfg = with_endpoint(None);
+        assert_eq!(cfg.reporting.format, "json");
+        cfg.reporting.format = "msgpack".to_string();
+        cfg.validate_input().unwrap();
+
+        cfg.reporting.format = "xml".to_string();
+        let err = cfg.validate_input().unwrap_err();
+        assert!(err.to_string().contains("'xml'"), "{}", err);
+    }
+
+    #[test]
+    fn test_report_fields() {
+        let mut cfg = with_endpoint(None);
+        assert_eq!(cfg.collecting.report_fields, None);
+
+        let collecting = fragments::CollectingFragment {
+            report_fields: Some(vec!["platform".to_string(), "instance_type".to_string()]),
+            exclude_fields: Some(vec!["nosuchfield".to_string()]),
+            ..Default::default()
+        };
+        cfg.collecting = CollectingInput::from_fragments(vec![collecting]);
+        assert_eq!(
+            cfg.collecting.report_fields,
+            Some(vec!["platform".to_string(), "instance_type".to_string()])
+        );
+        assert_eq!(
+            cfg.collecting.exclude_fields,
+            Some(vec!["nosuchfield".to_string()])
+        );
+
+        // Unknown excluded fields are only warned about.
+        cfg.validate_input().unwrap();
+
+        cfg.collecting.report_fields = Some(vec!["nosuchfield".to_string()]);
+        let err = cfg.validate_input().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown field 'nosuchfield' in `collecting.report_fields`"
+        );
+    }
+
+    #[test]
+    fn test_endpoint_default() {
+        let cfg = with_endpoint(None);
+        assert_eq!(cfg.reporting.endpoint, None);
+        cfg.validate_input().unwrap();
+    }
+
+    #[test]
+    fn test_endpoint_valid() {
+        let cfg = with_endpoint(Some("https://example.com/report"));
+        assert_eq!(
+            cfg.reporting.endpoint,
+            Some("https://example.com/report".to_string())
+        );
+        cfg.validate_input().unwrap();
+    }
+
+    #[test]
+    fn test_endpoint_invalid() {
+        let tests = vec!["not a url", "http://example.com/report", "ftp://example.com/"];
+        for endpoint in tests {
+            let cfg = with_endpoint(Some(endpoint));
+            let err = cfg.validate_input().unwrap_err();
+            assert!(
+                err.to_string().contains(endpoint),
+                "unexpected error for '{}': {}",
+                endpoint,
+                err
+            );
+
+            let mut cfg = with_endpoint(Some(endpoint));
+            cfg.reporting.enabled = Some(false);
+            cfg.validate_input().unwrap();
+        }
+    }
 }
@@ -0,0 +1,52 @@
+//! Crate-level error type.
+
+use crate::config::inputs::InvalidLevelError;
+use crate::identity::{AlephError, PlatformDetectionError};
+use thiserror::Error;
+
+/// Error returned when collecting the machine identity fails.
+///
+/// Only the data every report needs is required: failures to query
+/// rpm-ostree or to read optional fields are logged, and those fields left
+/// out, instead.
+#[derive(Debug, Error)]
+pub enum PingerError {
+    /// The collecting level is not valid.
+    #[error(transparent)]
+    InvalidLevel(#[from] InvalidLevelError),
+    /// The platform could not be detected.
+    #[error(transparent)]
+    PlatformDetection(#[from] PlatformDetectionError),
+    /// The aleph version file could not be read.
+    #[error(transparent)]
+    Aleph(#[from] AlephError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_from_aleph_error() {
+        let err = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let err = PingerError::from(AlephError::MetadataParse {
+            path: "/aleph.json".to_string(),
+            source: err,
+        });
+        assert!(
+            err.to_string()
+                .starts_with("failed to parse aleph version file '/aleph.json': "),
+            "{}",
+            err
+        );
+        let source = err.source().unwrap();
+        assert!(source.downcast_ref::<serde_json::Error>().is_some());
+        match err {
+            PingerError::Aleph(AlephError::MetadataParse { path, .. }) => {
+                assert_eq!(path, "/aleph.json")
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+}
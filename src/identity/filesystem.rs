@@ -0,0 +1,131 @@
+//! Filesystem information - utility functions
+
+use failure::{bail, format_err, Fallible, ResultExt};
+use std::ffi::CString;
+
+/// Size and block counts of a filesystem, as returned by `statvfs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FsStats {
+    /// Fragment size in bytes, the unit of `blocks`.
+    pub(crate) fragment_size: u64,
+    /// Total number of blocks.
+    pub(crate) blocks: u64,
+}
+
+/// Read the type of the filesystem mounted at `/` from `/proc/mounts`.
+pub(crate) fn root_fs_type(mounts_path: &str) -> Fallible<String> {
+    let contents = std::fs::read_to_string(mounts_path)
+        .context(format!("failed to read mounts file '{}'", mounts_path))?;
+    parse_root_fs_type(&contents)
+        .ok_or_else(|| format_err!("no root filesystem in mounts file '{}'", mounts_path))
+}
+
+/// Parse the type of the filesystem mounted at `/` from `/proc/mounts` content.
+///
+/// The root may be listed several times (e.g. `rootfs` from early boot), in
+/// which case the last mount is the one in use.
+fn parse_root_fs_type(contents: &str) -> Option<String> {
+    contents.lines().rev().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(_), Some("/"), Some(fs_type)) => Some(fs_type.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Read the total size of the filesystem holding `path`, rounded to GB
+/// (10^9 bytes).
+///
+/// For bind-mounted or overlay roots, this is the size of the backing
+/// filesystem.
+pub(crate) fn disk_size_gb(path: &str) -> Fallible<u64> {
+    disk_size_gb_with(|| statvfs(path))
+}
+
+/// Compute the total size of a filesystem, rounded to GB, from the stats
+/// returned by `stat`.
+fn disk_size_gb_with<F>(stat: F) -> Fallible<u64>
+where
+    F: FnOnce() -> Fallible<FsStats>,
+{
+    const GB: u64 = 1_000_000_000;
+    let stats = stat()?;
+    let bytes = stats.fragment_size.saturating_mul(stats.blocks);
+    Ok(bytes.saturating_add(GB / 2) / GB)
+}
+
+/// Query the size and block counts of the filesystem holding `path`.
+fn statvfs(path: &str) -> Fallible<FsStats> {
+    let c_path = CString::new(path).context(format!("invalid path '{}'", path))?;
+    let mut buf: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid NUL-terminated string and `buf` a valid
+    // `statvfs` structure, both outliving the call.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut buf) } != 0 {
+        let err = std::io::Error::last_os_error();
+        bail!("failed to stat filesystem at '{}': {}", path, err);
+    }
+    Ok(FsStats {
+        fragment_size: buf.f_frsize as u64,
+        blocks: buf.f_blocks as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_fs_type() {
+        let tests = vec![
+            ("tests/fixtures/proc-mounts-xfs", "xfs"),
+            ("tests/fixtures/proc-mounts-btrfs", "btrfs"),
+        ];
+        for (fpath, expected) in tests {
+            assert_eq!(root_fs_type(fpath).unwrap(), expected);
+        }
+
+        root_fs_type("tests/fixtures/proc-uptime").unwrap_err();
+        root_fs_type("/nonexistent").unwrap_err();
+    }
+
+    #[test]
+    fn test_disk_size_gb() {
+        // 120 GiB disk, with 4 KiB fragments.
+        let stats = FsStats {
+            fragment_size: 4096,
+            blocks: 31_457_280,
+        };
+        assert_eq!(disk_size_gb_with(|| Ok(stats)).unwrap(), 129);
+
+        let stats = FsStats {
+            fragment_size: 512,
+            blocks: 999_999,
+        };
+        assert_eq!(disk_size_gb_with(|| Ok(stats)).unwrap(), 1);
+        let stats = FsStats {
+            fragment_size: 512,
+            blocks: 976_562,
+        };
+        assert_eq!(disk_size_gb_with(|| Ok(stats)).unwrap(), 0);
+
+        disk_size_gb_with(|| bail!("statvfs failed")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_root_fs_type() {
+        let tests = vec![
+            ("/dev/sda1 / ext4 rw 0 0\n", Some("ext4")),
+            ("/dev/sda1 /var ext4 rw 0 0\n", None),
+            ("", None),
+        ];
+        for (contents, expected) in tests {
+            assert_eq!(
+                parse_root_fs_type(contents),
+                expected.map(String::from),
+                "failed testcase: '{}'",
+                contents
+            );
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Boot firmware - utility functions
+
+use failure::{bail, Fallible, ResultExt};
+use std::fmt;
+use std::path::Path;
+
+/// EFI variable holding the Secure Boot state, under the global variable GUID.
+const SECURE_BOOT_EFIVAR: &str = "SecureBoot-8be4df61-93ca-11d2-aa0d-00e098032b8c";
+
+/// Firmware boot mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BootMode {
+    Uefi,
+    Bios,
+}
+
+impl fmt::Display for BootMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self {
+            BootMode::Uefi => "uefi",
+            BootMode::Bios => "bios",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+/// Return the mode the system booted in, based on the presence of EFI
+/// entries under the given firmware directory (usually `/sys/firmware`).
+pub(crate) fn boot_mode(firmware_dir: &str) -> BootMode {
+    if Path::new(firmware_dir).join("efi").is_dir() {
+        BootMode::Uefi
+    } else {
+        BootMode::Bios
+    }
+}
+
+/// Return whether Secure Boot is enabled, reading the `SecureBoot` EFI
+/// variable under the given firmware directory (usually `/sys/firmware`).
+///
+/// The variable is absent on non-UEFI systems, on which Secure Boot is
+/// reported as disabled.
+pub(crate) fn secure_boot_enabled(firmware_dir: &str) -> Fallible<bool> {
+    let var_path = Path::new(firmware_dir)
+        .join("efi/efivars")
+        .join(SECURE_BOOT_EFIVAR);
+    if !var_path.exists() {
+        return Ok(false);
+    }
+
+    let contents = std::fs::read(&var_path).context(format!(
+        "failed to read EFI variable '{}'",
+        var_path.display()
+    ))?;
+    // The variable holds 4 bytes of attributes, followed by a 1-byte flag.
+    match contents.as_slice() {
+        [_, _, _, _, value] => Ok(*value == 1),
+        _ => bail!("invalid EFI variable '{}'", var_path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boot_mode() {
+        assert_eq!(boot_mode("tests/fixtures/firmware-uefi"), BootMode::Uefi);
+        assert_eq!(boot_mode("/nonexistent/firmware"), BootMode::Bios);
+    }
+
+    #[test]
+    fn test_secure_boot_enabled() {
+        let tests = vec![
+            ("tests/fixtures/firmware-uefi", true),
+            ("tests/fixtures/firmware-uefi-secureboot-disabled", false),
+            ("/nonexistent/firmware", false),
+        ];
+        for (dir, expected) in tests {
+            assert_eq!(
+                secure_boot_enabled(dir).unwrap(),
+                expected,
+                "failed testcase: {}",
+                dir
+            );
+        }
+    }
+
+    #[test]
+    fn test_secure_boot_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let efivars = dir.path().join("efi/efivars");
+        std::fs::create_dir_all(&efivars).unwrap();
+        let dir = dir.path().to_str().unwrap();
+
+        for contents in &[&[][..], &[6, 0, 0, 0][..], &[6, 0, 0, 0, 1, 0][..]] {
+            std::fs::write(efivars.join(SECURE_BOOT_EFIVAR), contents).unwrap();
+            secure_boot_enabled(dir).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_boot_mode_display() {
+        assert_eq!(BootMode::Uefi.to_string(), "uefi");
+        assert_eq!(BootMode::Bios.to_string(), "bios");
+    }
+}
@@ -0,0 +1,47 @@
+//! Serialization formats of reports.
+
+/// Format in which reports are serialized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// JSON document.
+    Json,
+    /// MessagePack document, with named fields.
+    MessagePack,
+}
+
+impl Format {
+    /// Parse a configured format.
+    pub(crate) fn parse(format: &str) -> Option<Self> {
+        match format {
+            "json" => Some(Format::Json),
+            "msgpack" => Some(Format::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// Return the format of a file with the given extension, as written
+    /// with `extension`.
+    pub(crate) fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "json" => Some(Format::Json),
+            "msgpack" => Some(Format::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// File extension of documents in this format.
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::MessagePack => "msgpack",
+        }
+    }
+
+    /// HTTP `Content-Type` of documents in this format.
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::MessagePack => "application/msgpack",
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Hardware and runtime information - utility functions
+
+use failure::{format_err, Fallible, ResultExt};
+use std::fmt;
+
+/// Error returned when `/proc/meminfo` has no valid `MemTotal` entry.
+#[derive(Debug)]
+pub(crate) struct MissingMemTotalError {
+    fpath: String,
+}
+
+impl fmt::Display for MissingMemTotalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no valid MemTotal entry in '{}'", self.fpath)
+    }
+}
+
+impl std::error::Error for MissingMemTotalError {}
+
+/// Error returned when no CPU is listed as online.
+#[derive(Debug)]
+pub(crate) struct NoProcessorsError {
+    fpath: String,
+}
+
+impl fmt::Display for NoProcessorsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no online CPUs in '{}'", self.fpath)
+    }
+}
+
+impl std::error::Error for NoProcessorsError {}
+
+/// Read the time since boot from `/proc/uptime`, rounded to whole seconds.
+pub(crate) fn uptime_secs(fpath: &str) -> Fallible<u64> {
+    let contents = std::fs::read_to_string(fpath)
+        .context(format!("failed to read uptime file '{}'", fpath))?;
+    parse_uptime(&contents).ok_or_else(|| format_err!("invalid uptime file '{}'", fpath))
+}
+
+/// Parse the first field of `/proc/uptime` content.
+fn parse_uptime(contents: &str) -> Option<u64> {
+    let secs: f64 = contents.split_whitespace().next()?.parse().ok()?;
+    if secs.is_sign_negative() || !secs.is_finite() {
+        return None;
+    }
+    Some(secs.round() as u64)
+}
+
+/// Read the total system memory from `/proc/meminfo`, in kB.
+pub(crate) fn total_memory_kb(fpath: &str) -> Fallible<u64> {
+    let contents = std::fs::read_to_string(fpath)
+        .context(format!("failed to read meminfo file '{}'", fpath))?;
+    match parse_mem_total_kb(&contents) {
+        Some(kb) => Ok(kb),
+        None => Err(MissingMemTotalError {
+            fpath: fpath.to_string(),
+        }
+        .into()),
+    }
+}
+
+/// Parse the `MemTotal` entry (in kB) of `/proc/meminfo` content.
+fn parse_mem_total_kb(contents: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some("MemTotal:"), Some(value), Some("kB"))
+            | (Some("MemTotal:"), Some(value), None) => value.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+/// Count the online logical CPUs, from `/sys/devices/system/cpu/online`.
+pub(crate) fn cpu_count(fpath: &str) -> Fallible<usize> {
+    let contents = std::fs::read_to_string(fpath)
+        .context(format!("failed to read CPU list file '{}'", fpath))?;
+    let count = parse_cpu_list(&contents)
+        .ok_or_else(|| format_err!("invalid CPU list file '{}'", fpath))?;
+    if count == 0 {
+        let err = NoProcessorsError {
+            fpath: fpath.to_string(),
+        };
+        return Err(err.into());
+    }
+
+    Ok(count)
+}
+
+/// Count the CPUs in a CPU list, e.g. `0-3,5`.
+fn parse_cpu_list(contents: &str) -> Option<usize> {
+    let contents = contents.trim();
+    if contents.is_empty() {
+        return Some(0);
+    }
+
+    let mut count = 0;
+    for range in contents.split(',') {
+        let mut bounds = range.splitn(2, '-');
+        let first: usize = bounds.next()?.trim().parse().ok()?;
+        let last: usize = match bounds.next() {
+            Some(last) => last.trim().parse().ok()?,
+            None => first,
+        };
+        if last < first {
+            return None;
+        }
+        count += last - first + 1;
+    }
+
+    Some(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uptime() {
+        let tests = vec![
+            ("12345.67 9876.54\n", Some(12346)),
+            ("12345.12 9876.54\n", Some(12345)),
+            ("0.00 0.00", Some(0)),
+            ("", None),
+            ("abc 9876.54", None),
+            ("-1.00 0.00", None),
+        ];
+        for (contents, expected) in tests {
+            assert_eq!(
+                parse_uptime(contents),
+                expected,
+                "failed testcase: '{}'",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn test_uptime_secs() {
+        assert_eq!(uptime_secs("tests/fixtures/proc-uptime").unwrap(), 12346);
+        uptime_secs("/nonexistent").unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_mem_total_kb() {
+        let tests = vec![
+            (
+                "MemTotal:        4026348 kB\nMemFree: 1 kB\n",
+                Some(4_026_348),
+            ),
+            ("MemFree: 1 kB\n  MemTotal:\t2048   kB  \n", Some(2048)),
+            ("MemTotal: 2048", Some(2048)),
+            ("MemFree: 1 kB\n", None),
+            ("MemTotal: lots kB\n", None),
+            ("", None),
+        ];
+        for (contents, expected) in tests {
+            assert_eq!(
+                parse_mem_total_kb(contents),
+                expected,
+                "failed testcase: '{}'",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn test_total_memory_kb() {
+        assert_eq!(
+            total_memory_kb("tests/fixtures/proc-meminfo").unwrap(),
+            4_026_348
+        );
+
+        let err = total_memory_kb("tests/fixtures/proc-uptime").unwrap_err();
+        assert!(err.downcast_ref::<MissingMemTotalError>().is_some());
+        total_memory_kb("/nonexistent").unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        let tests = vec![
+            ("0-3\n", Some(4)),
+            ("0-3,5\n", Some(5)),
+            ("0-1,4-7,9", Some(7)),
+            ("0\n", Some(1)),
+            ("\n", Some(0)),
+            ("3-1", None),
+            ("0-", None),
+            ("a-b", None),
+        ];
+        for (contents, expected) in tests {
+            assert_eq!(
+                parse_cpu_list(contents),
+                expected,
+                "failed testcase: '{}'",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn test_cpu_count() {
+        assert_eq!(cpu_count("tests/fixtures/cpu-online").unwrap(), 4);
+
+        let err = cpu_count("tests/fixtures/cpu-online-empty").unwrap_err();
+        assert!(err.downcast_ref::<NoProcessorsError>().is_some());
+
+        let err = cpu_count("tests/fixtures/proc-uptime").unwrap_err();
+        assert!(err.downcast_ref::<NoProcessorsError>().is_none());
+
+        let err = cpu_count("/nonexistent").unwrap_err();
+        assert!(err.downcast_ref::<NoProcessorsError>().is_none());
+    }
+}
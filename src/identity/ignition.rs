@@ -0,0 +1,42 @@
+//! Ignition provisioning information - utility functions
+
+use failure::{bail, Fallible, ResultExt};
+use serde::Deserialize;
+use std::io;
+
+/// Result marker written by Ignition on first boot.
+#[derive(Debug, Deserialize)]
+struct IgnitionResult {
+    /// Spec version of the config used for provisioning.
+    #[serde(rename = "specVersion")]
+    spec_version: Option<String>,
+}
+
+/// Read the Ignition spec version used at first boot from the result marker.
+///
+/// Older images do not write the marker, in which case `None` is returned.
+pub(crate) fn read_spec_version(fpath: &str) -> Fallible<Option<String>> {
+    let file = match std::fs::File::open(fpath) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => bail!("failed to open Ignition result file '{}': {}", fpath, e),
+    };
+    let result: IgnitionResult = serde_json::from_reader(io::BufReader::new(file))
+        .context(format!("failed to parse Ignition result file '{}'", fpath))?;
+
+    Ok(result.spec_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_spec_version() {
+        let version = read_spec_version("tests/fixtures/ignition-result.json").unwrap();
+        assert_eq!(version, Some("3.3.0".to_string()));
+
+        assert_eq!(read_spec_version("/nonexistent").unwrap(), None);
+        read_spec_version("tests/fixtures/aleph-version-malformed.json").unwrap_err();
+    }
+}
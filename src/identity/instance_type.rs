@@ -0,0 +1,295 @@
+//! Instance type parsing from Afterburn metadata - utility functions
+
+use super::Platform;
+use failure::{Fallible, ResultExt};
+use log::debug;
+use std::io::{BufRead, BufReader};
+
+/// Granularity of the reported instance type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum InstanceTypeGranularity {
+    /// The instance type as collected, e.g. `m5.large`.
+    Full,
+    /// Only the instance family, e.g. `m5`.
+    Family,
+    /// No instance type.
+    None,
+}
+
+impl InstanceTypeGranularity {
+    /// Parse a configured granularity.
+    pub(crate) fn parse(granularity: &str) -> Option<Self> {
+        match granularity {
+            "full" => Some(InstanceTypeGranularity::Full),
+            "family" => Some(InstanceTypeGranularity::Family),
+            "none" => Some(InstanceTypeGranularity::None),
+            _ => None,
+        }
+    }
+
+    /// Reduce the collected instance type to this granularity.
+    pub(crate) fn apply(self, instance_type: Option<String>) -> Option<String> {
+        match self {
+            InstanceTypeGranularity::Full => instance_type,
+            InstanceTypeGranularity::Family => instance_type.map(|t| instance_family(&t)),
+            InstanceTypeGranularity::None => None,
+        }
+    }
+}
+
+/// Return the family of an instance type, i.e. its first component, for both
+/// AWS-style (`m5.large`) and GCP-style (`n1-standard-1`) names.
+fn instance_family(instance_type: &str) -> String {
+    instance_type
+        .split(['.', '-'])
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Afterburn metadata key holding the instance type, per platform.
+///
+/// Only attributes documented in Afterburn's `docs/usage/attributes.md`
+/// (<https://coreos.github.io/afterburn/usage/attributes/>) are listed.
+/// Other platforms, e.g. DigitalOcean, Hetzner, IBM Cloud, OpenStack,
+/// PowerVS, VMware or Vultr, do not provide an instance type.
+const INSTANCE_TYPE_KEYS: &[(Platform, &str)] = &[
+    (Platform::Aliyun, "AFTERBURN_ALIYUN_INSTANCE_TYPE"),
+    (Platform::Aws, "AFTERBURN_AWS_INSTANCE_TYPE"),
+    (Platform::Azure, "AFTERBURN_AZURE_VMSIZE"),
+    (Platform::Gcp, "AFTERBURN_GCP_MACHINE_TYPE"),
+];
+
+/// Return the Afterburn metadata key holding the instance type for the
+/// given platform, if the platform exposes one.
+fn metadata_key(platform: &Platform) -> Option<&'static str> {
+    INSTANCE_TYPE_KEYS
+        .iter()
+        .find(|(p, _)| p == platform)
+        .map(|(_, key)| *key)
+}
+
+/// Read the instance type for the given platform from the Afterburn metadata file.
+///
+/// Returns `None` if the platform does not expose an instance type or if the
+/// metadata file does not contain it.
+pub(crate) fn read_instance_type(fpath: &str, platform: &Platform) -> Fallible<Option<String>> {
+    match metadata_key(platform) {
+        Some(key) => read_metadata_value(fpath, key),
+        None => Ok(None),
+    }
+}
+
+/// Read the AWS region from the Afterburn metadata file.
+///
+/// Returns `None` on platforms other than AWS.
+pub(crate) fn read_region(fpath: &str, platform: &Platform) -> Fallible<Option<String>> {
+    match platform {
+        Platform::Aws => read_metadata_value(fpath, "AFTERBURN_AWS_REGION"),
+        _ => Ok(None),
+    }
+}
+
+/// Read the AWS availability zone from the Afterburn metadata file.
+///
+/// Returns `None` on platforms other than AWS.
+pub(crate) fn read_availability_zone(fpath: &str, platform: &Platform) -> Fallible<Option<String>> {
+    match platform {
+        Platform::Aws => read_metadata_value(fpath, "AFTERBURN_AWS_AVAILABILITY_ZONE"),
+        _ => Ok(None),
+    }
+}
+
+/// Read the value of the given key from the Afterburn metadata file.
+///
+/// Keys are matched case-insensitively. Blank, comment and malformed lines
+/// (without a `=` separator) are skipped. Returns `None` if the file does not
+/// contain the key.
+fn read_metadata_value(fpath: &str, key: &str) -> Fallible<Option<String>> {
+    let file =
+        std::fs::File::open(fpath).context(format!("failed to open metadata file '{}'", fpath))?;
+    for line in BufReader::new(file).lines() {
+        let line = line.context(format!("failed to read metadata file '{}'", fpath))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let kv: Vec<&str> = line.splitn(2, '=').collect();
+        if kv.len() == 2 && kv[0].trim().eq_ignore_ascii_case(key) {
+            return Ok(Some(kv[1].trim().to_string()));
+        }
+    }
+
+    debug!("key '{}' not found in metadata file '{}'", key, fpath);
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aws_instance_type() {
+        let instance_type =
+            read_instance_type("tests/fixtures/afterburn-aws", &Platform::Aws).unwrap();
+        assert_eq!(instance_type, Some("m5.large".to_string()));
+    }
+
+    #[test]
+    fn test_aws_placement() {
+        let fpath = "tests/fixtures/afterburn-aws";
+        assert_eq!(
+            read_region(fpath, &Platform::Aws).unwrap(),
+            Some("us-east-1".to_string())
+        );
+        assert_eq!(
+            read_availability_zone(fpath, &Platform::Aws).unwrap(),
+            Some("us-east-1c".to_string())
+        );
+    }
+
+    #[test]
+    fn test_non_aws_placement() {
+        // No metadata is read on other platforms.
+        assert_eq!(read_region("/nonexistent", &Platform::Gcp).unwrap(), None);
+        assert_eq!(
+            read_availability_zone("/nonexistent", &Platform::Gcp).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_platform_instance_types() {
+        let tests = vec![
+            ("aliyun", "ecs.g6.large"),
+            ("aws", "m5.large"),
+            ("azure", "Standard_D2s_v3"),
+            ("gcp", "n1-standard-1"),
+        ];
+        for (platform, expected) in tests {
+            let fpath = format!("tests/fixtures/afterburn-{}", platform);
+            let instance_type = read_instance_type(&fpath, &Platform::from_id(platform)).unwrap();
+            assert_eq!(
+                instance_type,
+                Some(expected.to_string()),
+                "failed platform: {}",
+                platform
+            );
+        }
+
+        // Azure does not use the generic instance type key.
+        let missing = read_instance_type("tests/fixtures/afterburn-aws", &Platform::Azure).unwrap();
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn test_undocumented_instance_type() {
+        // No metadata is read on platforms without a documented attribute.
+        let platforms = vec![
+            Platform::DigitalOcean,
+            Platform::Hetzner,
+            Platform::IbmCloud,
+            Platform::OpenStack,
+            Platform::OracleCloud,
+        ];
+        for platform in platforms {
+            let instance_type = read_instance_type("/nonexistent", &platform).unwrap();
+            assert_eq!(instance_type, None, "failed platform: {}", platform);
+        }
+    }
+
+    #[test]
+    fn test_powervs_no_instance_type() {
+        let instance_type = read_instance_type("/nonexistent", &Platform::PowerVs).unwrap();
+        assert_eq!(instance_type, None);
+    }
+
+    #[test]
+    fn test_vultr_no_instance_type() {
+        let instance_type = read_instance_type("/nonexistent", &Platform::Vultr).unwrap();
+        assert_eq!(instance_type, None);
+    }
+
+    #[test]
+    fn test_missing_instance_type() {
+        // The AWS metadata fixture has no GCP machine type.
+        let instance_type =
+            read_instance_type("tests/fixtures/afterburn-aws", &Platform::Gcp).unwrap();
+        assert_eq!(instance_type, None);
+    }
+
+    #[test]
+    fn test_malformed_metadata() {
+        let fpath = "tests/fixtures/afterburn-malformed";
+        assert_eq!(
+            read_instance_type(fpath, &Platform::Aws).unwrap(),
+            Some("t3.micro".to_string())
+        );
+        assert_eq!(
+            read_region(fpath, &Platform::Aws).unwrap(),
+            Some("eu-west-1".to_string())
+        );
+
+        assert_eq!(read_availability_zone(fpath, &Platform::Aws).unwrap(), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("metadata");
+        std::fs::write(&fpath, b"AFTERBURN_AWS_INSTANCE_TYPE=\xff\xfe\n").unwrap();
+        read_instance_type(fpath.to_str().unwrap(), &Platform::Aws).unwrap_err();
+    }
+
+    #[test]
+    fn test_missing_metadata_file() {
+        read_instance_type("/nonexistent", &Platform::Aws).unwrap_err();
+    }
+
+    #[test]
+    fn test_vmware_no_instance_type() {
+        // No metadata is read for VMware, so a missing file is not an error.
+        let instance_type = read_instance_type("/nonexistent", &Platform::VMware).unwrap();
+        assert_eq!(instance_type, None);
+    }
+
+    #[test]
+    fn test_parse_granularity() {
+        let tests = vec![
+            ("full", Some(InstanceTypeGranularity::Full)),
+            ("family", Some(InstanceTypeGranularity::Family)),
+            ("none", Some(InstanceTypeGranularity::None)),
+            ("Family", None),
+            ("", None),
+        ];
+        for (granularity, expected) in tests {
+            assert_eq!(
+                InstanceTypeGranularity::parse(granularity),
+                expected,
+                "failed testcase: '{}'",
+                granularity
+            );
+        }
+    }
+
+    #[test]
+    fn test_granularity() {
+        let tests = vec![
+            ("m5.large", "m5.large", "m5"),
+            ("t3a.2xlarge", "t3a.2xlarge", "t3a"),
+            ("n1-standard-1", "n1-standard-1", "n1"),
+            ("e2-medium", "e2-medium", "e2"),
+            ("cx22", "cx22", "cx22"),
+        ];
+        for (instance_type, full, family) in tests {
+            let instance_type = Some(instance_type.to_string());
+            assert_eq!(
+                InstanceTypeGranularity::Full.apply(instance_type.clone()),
+                Some(full.to_string())
+            );
+            assert_eq!(
+                InstanceTypeGranularity::Family.apply(instance_type.clone()),
+                Some(family.to_string())
+            );
+            assert_eq!(InstanceTypeGranularity::None.apply(instance_type), None);
+        }
+        assert_eq!(InstanceTypeGranularity::Family.apply(None), None);
+    }
+}
@@ -0,0 +1,63 @@
+//! Kernel information - utility functions
+
+use failure::{bail, Fallible, ResultExt};
+use std::io;
+
+/// Read the release of the running kernel from `/proc/sys/kernel/osrelease`.
+pub(crate) fn version(fpath: &str) -> Fallible<String> {
+    let contents = std::fs::read_to_string(fpath)
+        .context(format!("failed to read kernel release file '{}'", fpath))?;
+    let version = contents.trim();
+    if version.is_empty() {
+        bail!("empty kernel release in '{}'", fpath);
+    }
+
+    Ok(version.to_string())
+}
+
+/// Read whether the kernel runs in FIPS mode from `/proc/sys/crypto/fips_enabled`.
+///
+/// The file is absent on kernels built without FIPS support, which cannot be
+/// in FIPS mode.
+pub(crate) fn fips_enabled(fpath: &str) -> Fallible<bool> {
+    let contents = match std::fs::read_to_string(fpath) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => bail!("failed to read FIPS mode file '{}': {}", fpath, e),
+    };
+    match contents.trim() {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        value => bail!("invalid FIPS mode '{}' in '{}'", value, fpath),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert_eq!(
+            version("tests/fixtures/kernel-osrelease").unwrap(),
+            "5.3.7-301.fc31.x86_64"
+        );
+
+        version("tests/fixtures/machine-id-empty").unwrap_err();
+        version("/nonexistent").unwrap_err();
+    }
+
+    #[test]
+    fn test_fips_enabled() {
+        let tests = vec![
+            ("tests/fixtures/fips-enabled-1", true),
+            ("tests/fixtures/fips-enabled-0", false),
+            ("/nonexistent", false),
+        ];
+        for (fpath, expected) in tests {
+            assert_eq!(fips_enabled(fpath).unwrap(), expected, "failed: {}", fpath);
+        }
+
+        fips_enabled("tests/fixtures/kernel-osrelease").unwrap_err();
+    }
+}
@@ -0,0 +1,100 @@
+//! Kernel command-line arguments - utility functions
+
+use super::platform;
+use failure::{Fallible, ResultExt};
+use std::collections::BTreeMap;
+
+/// Kernel flags which are always reported, as they are not sensitive.
+const KERNEL_FLAGS: &[&str] = &["enforcing", "fips", "mitigations"];
+
+/// Read the kernel command-line and return the arguments whose key is in
+/// `allowlist`.
+pub(crate) fn read_kernel_args(
+    fpath: &str,
+    allowlist: &[String],
+) -> Fallible<BTreeMap<String, String>> {
+    if allowlist.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let cmdline = std::fs::read_to_string(fpath)
+        .context(format!("failed to read cmdline file '{}'", fpath))?;
+    let args = parse_cmdline(&cmdline)
+        .into_iter()
+        .filter(|(key, _)| allowlist.contains(key))
+        .collect();
+
+    Ok(args)
+}
+
+/// Read the kernel command-line and return the curated flags in
+/// `KERNEL_FLAGS` which are set.
+pub(crate) fn read_kernel_flags(fpath: &str) -> Fallible<BTreeMap<String, String>> {
+    let allowlist: Vec<String> = KERNEL_FLAGS.iter().map(|f| f.to_string()).collect();
+    read_kernel_args(fpath, &allowlist)
+}
+
+/// Parse kernel command-line content into a key-value map.
+///
+/// Arguments without a value (e.g. `rw`) map to an empty string. If an
+/// argument is repeated, the last occurrence wins.
+fn parse_cmdline(cmdline: &str) -> BTreeMap<String, String> {
+    platform::parse_kernel_flags(cmdline)
+        .into_iter()
+        .map(|(key, value)| (key, value.unwrap_or_default()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cmdline() {
+        let args = parse_cmdline("rw mitigations=auto,nosmt console=tty0 console=ttyS0 a=b=c\n");
+        let expected: BTreeMap<String, String> = vec![
+            ("rw", ""),
+            ("mitigations", "auto,nosmt"),
+            ("console", "ttyS0"),
+            ("a", "b=c"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        assert_eq!(args, expected);
+
+        assert!(parse_cmdline("").is_empty());
+    }
+
+    #[test]
+    fn test_read_kernel_args() {
+        let allowlist = vec!["mitigations".to_string(), "rootflags".to_string()];
+        let args = read_kernel_args("tests/fixtures/cmdline-aws", &allowlist).unwrap();
+        assert_eq!(args.len(), 2);
+        assert_eq!(args["mitigations"], "auto,nosmt");
+        assert_eq!(args["rootflags"], "prjquota");
+
+        let args = read_kernel_args("tests/fixtures/cmdline-aws", &[]).unwrap();
+        assert!(args.is_empty());
+
+        // Nothing is read without an allowlist.
+        assert!(read_kernel_args("/nonexistent", &[]).unwrap().is_empty());
+        read_kernel_args("/nonexistent", &allowlist).unwrap_err();
+    }
+
+    #[test]
+    fn test_read_kernel_flags() {
+        let flags = read_kernel_flags("tests/fixtures/cmdline-fips").unwrap();
+        let expected: BTreeMap<String, String> =
+            vec![("enforcing", "0"), ("fips", "1"), ("mitigations", "off")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+        assert_eq!(flags, expected);
+
+        let flags = read_kernel_flags("tests/fixtures/cmdline-aws").unwrap();
+        assert_eq!(flags.keys().collect::<Vec<_>>(), vec!["mitigations"]);
+
+        read_kernel_flags("/nonexistent").unwrap_err();
+    }
+}
@@ -0,0 +1,83 @@
+//! Collecting levels.
+
+use serde::Serialize;
+use std::fmt;
+
+/// Level of detail of the collected data.
+///
+/// Levels are ordered, each one collecting everything the lower levels do.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CollectingLevel {
+    /// Nothing is collected.
+    #[default]
+    None,
+    /// Basic OS and platform information.
+    Minimal,
+    /// Additional hardware and configuration details.
+    Full,
+}
+
+impl CollectingLevel {
+    /// Parse a configured level, `"off"` being an alias of `"none"`.
+    pub(crate) fn parse(level: &str) -> Option<Self> {
+        match level {
+            "none" | "off" => Some(CollectingLevel::None),
+            "minimal" => Some(CollectingLevel::Minimal),
+            "full" => Some(CollectingLevel::Full),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CollectingLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self {
+            CollectingLevel::None => "none",
+            CollectingLevel::Minimal => "minimal",
+            CollectingLevel::Full => "full",
+        };
+        write!(f, "{}", level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let tests = vec![
+            ("none", Some(CollectingLevel::None)),
+            ("off", Some(CollectingLevel::None)),
+            ("minimal", Some(CollectingLevel::Minimal)),
+            ("full", Some(CollectingLevel::Full)),
+            ("Full", None),
+            ("", None),
+        ];
+        for (level, expected) in tests {
+            assert_eq!(
+                CollectingLevel::parse(level),
+                expected,
+                "failed: '{}'",
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(CollectingLevel::None < CollectingLevel::Minimal);
+        assert!(CollectingLevel::Minimal < CollectingLevel::Full);
+        assert_eq!(CollectingLevel::default(), CollectingLevel::None);
+    }
+
+    #[test]
+    fn test_display() {
+        for level in &["none", "minimal", "full"] {
+            let parsed = CollectingLevel::parse(level).unwrap();
+            assert_eq!(parsed.to_string(), *level);
+            assert_eq!(serde_json::to_value(parsed).unwrap(), *level);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Machine ID - utility functions
+
+use failure::{bail, Fallible, ResultExt};
+
+/// Read the raw machine ID, which must not be reported as is.
+pub(super) fn read_machine_id(fpath: &str) -> Fallible<String> {
+    let contents = std::fs::read_to_string(fpath)
+        .context(format!("failed to read machine-id file '{}'", fpath))?;
+    let machine_id = contents.trim();
+    if machine_id.is_empty() {
+        bail!("empty machine-id file '{}'", fpath);
+    }
+
+    Ok(machine_id.to_string())
+}
+
+/// Encode bytes as a lowercase hex string.
+pub(super) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_machine_id() {
+        let id = read_machine_id("tests/fixtures/machine-id").unwrap();
+        let raw = std::fs::read_to_string("tests/fixtures/machine-id").unwrap();
+        assert_eq!(id, raw.trim());
+    }
+
+    #[test]
+    fn test_read_machine_id_invalid() {
+        let err = read_machine_id("tests/fixtures/machine-id-empty").unwrap_err();
+        assert!(err.to_string().contains("empty"), "unexpected error: {}", err);
+        read_machine_id("/nonexistent").unwrap_err();
+    }
+
+    #[test]
+    fn test_to_hex() {
+        assert_eq!(to_hex(&[]), "");
+        assert_eq!(to_hex(&[0x00, 0x0f, 0xa5, 0xff]), "000fa5ff");
+    }
+}
@@ -0,0 +1,1980 @@
+//! Collected identity of the running machine.
+
+mod filesystem;
+mod firmware;
+mod format;
+mod hardware;
+mod ignition;
+mod instance_type;
+mod kernel;
+mod kernel_args;
+mod level;
+mod machine_id;
+mod os_release;
+mod platform;
+mod privacy;
+mod selinux;
+mod virtualization;
+
+use crate::config::inputs;
+use crate::errors::PingerError;
+use crate::rpm_ostree;
+use failure::{Fallible, ResultExt};
+use log::warn;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+pub(crate) use format::Format;
+pub(crate) use instance_type::InstanceTypeGranularity;
+pub(crate) use level::CollectingLevel;
+pub use os_release::AlephError;
+pub(crate) use platform::Platform;
+pub use platform::PlatformDetectionError;
+
+/// Version of the JSON report schema, bumped whenever reported fields change.
+pub(crate) const SCHEMA_VERSION: u32 = 10;
+/// Version of the pinger, as packaged.
+const AGENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Kernel command-line, containing the platform ID.
+pub(crate) const KERNEL_ARGS_FILE: &str = "/proc/cmdline";
+/// DMI (SMBIOS) attributes directory, used as a platform ID fallback.
+const DMI_ID_DIR: &str = "/sys/class/dmi/id";
+/// Aleph version file, containing the OS version the machine was installed with.
+pub(crate) const OS_ALEPH_VERSION_FILE: &str = "/.coreos-aleph-version.json";
+/// Metadata attributes written by Afterburn.
+pub(crate) const AFTERBURN_METADATA: &str = "/run/metadata/afterburn";
+/// Time since boot.
+const UPTIME_FILE: &str = "/proc/uptime";
+/// Memory statistics.
+const MEMINFO_FILE: &str = "/proc/meminfo";
+/// List of online CPUs.
+const CPU_ONLINE_FILE: &str = "/sys/devices/system/cpu/online";
+/// Release of the running kernel.
+const KERNEL_OSRELEASE_FILE: &str = "/proc/sys/kernel/osrelease";
+/// FIPS mode of the kernel, absent if not supported.
+const FIPS_ENABLED_FILE: &str = "/proc/sys/crypto/fips_enabled";
+/// Mounted filesystems.
+const MOUNTS_FILE: &str = "/proc/mounts";
+/// Root directory, whose filesystem size is reported.
+const ROOT_DIR: &str = "/";
+/// Firmware information, containing EFI entries on UEFI systems.
+const FIRMWARE_DIR: &str = "/sys/firmware";
+/// SELinux filesystem, absent if SELinux is disabled.
+const SELINUXFS_DIR: &str = "/sys/fs/selinux";
+/// Ignition result marker, written on first boot.
+const IGNITION_RESULT_FILE: &str = "/etc/.ignition-result.json";
+/// Machine ID, only reported in anonymized form.
+const MACHINE_ID_FILE: &str = "/etc/machine-id";
+
+/// Locations of the files and directories identity data is read from.
+///
+/// Defaults to the live system paths above.
+#[derive(Clone, Debug)]
+pub(crate) struct Paths {
+    /// Kernel command-line.
+    pub(crate) kernel_args: String,
+    /// DMI (SMBIOS) attributes directory.
+    pub(crate) dmi_id_dir: String,
+    /// Aleph version file.
+    pub(crate) aleph_version: String,
+    /// Afterburn metadata attributes.
+    pub(crate) afterburn_metadata: String,
+    /// Time since boot.
+    pub(crate) uptime: String,
+    /// Memory statistics.
+    pub(crate) meminfo: String,
+    /// List of online CPUs.
+    pub(crate) cpu_online: String,
+    /// Release of the running kernel.
+    pub(crate) kernel_osrelease: String,
+    /// FIPS mode of the kernel.
+    pub(crate) fips_enabled: String,
+    /// Mounted filesystems.
+    pub(crate) mounts: String,
+    /// Root directory.
+    pub(crate) root_dir: String,
+    /// Firmware information directory.
+    pub(crate) firmware_dir: String,
+    /// SELinux filesystem.
+    pub(crate) selinuxfs_dir: String,
+    /// Ignition result marker.
+    pub(crate) ignition_result: String,
+    /// Machine ID.
+    pub(crate) machine_id: String,
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Self {
+            kernel_args: KERNEL_ARGS_FILE.to_string(),
+            dmi_id_dir: DMI_ID_DIR.to_string(),
+            aleph_version: OS_ALEPH_VERSION_FILE.to_string(),
+            afterburn_metadata: AFTERBURN_METADATA.to_string(),
+            uptime: UPTIME_FILE.to_string(),
+            meminfo: MEMINFO_FILE.to_string(),
+            cpu_online: CPU_ONLINE_FILE.to_string(),
+            kernel_osrelease: KERNEL_OSRELEASE_FILE.to_string(),
+            fips_enabled: FIPS_ENABLED_FILE.to_string(),
+            mounts: MOUNTS_FILE.to_string(),
+            root_dir: ROOT_DIR.to_string(),
+            firmware_dir: FIRMWARE_DIR.to_string(),
+            selinuxfs_dir: SELINUXFS_DIR.to_string(),
+            ignition_result: IGNITION_RESULT_FILE.to_string(),
+            machine_id: MACHINE_ID_FILE.to_string(),
+        }
+    }
+}
+
+impl Paths {
+    /// Default paths, with the overrides from configuration.
+    pub(crate) fn from_config(cfg: &inputs::CollectingInput) -> Self {
+        Self {
+            kernel_args: cfg.kernel_args_path.clone(),
+            aleph_version: cfg.aleph_version_path.clone(),
+            afterburn_metadata: cfg.afterburn_metadata_path.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Resolve all paths relative to the given root directory instead of
+    /// `/`, e.g. to collect data from a mounted image.
+    pub(crate) fn with_root(self, root: &Path) -> Self {
+        let rooted = |path: String| {
+            root.join(path.trim_start_matches('/'))
+                .to_string_lossy()
+                .into_owned()
+        };
+        Self {
+            kernel_args: rooted(self.kernel_args),
+            dmi_id_dir: rooted(self.dmi_id_dir),
+            aleph_version: rooted(self.aleph_version),
+            afterburn_metadata: rooted(self.afterburn_metadata),
+            uptime: rooted(self.uptime),
+            meminfo: rooted(self.meminfo),
+            cpu_online: rooted(self.cpu_online),
+            kernel_osrelease: rooted(self.kernel_osrelease),
+            fips_enabled: rooted(self.fips_enabled),
+            mounts: rooted(self.mounts),
+            root_dir: rooted(self.root_dir),
+            firmware_dir: rooted(self.firmware_dir),
+            selinuxfs_dir: rooted(self.selinuxfs_dir),
+            ignition_result: rooted(self.ignition_result),
+            machine_id: rooted(self.machine_id),
+        }
+    }
+}
+
+/// Machine identity, as collected for reporting.
+#[derive(Debug, Default, Serialize)]
+pub struct Identity {
+    /// Collecting level.
+    pub(crate) level: CollectingLevel,
+    /// OS platform.
+    pub(crate) platform: Platform,
+    /// CPU architecture.
+    pub(crate) architecture: String,
+    /// Version of the pinger producing the report.
+    pub(crate) agent_version: String,
+    /// OS version at install time, if the aleph version file exists.
+    pub(crate) original_os_version: Option<String>,
+    /// Fedora major version of the OS at install time, if the version is well-formed.
+    pub(crate) original_major_version: Option<u32>,
+    /// ID of the image the machine was installed from, if recorded.
+    pub(crate) original_image_id: Option<String>,
+    /// OS version of the booted deployment, if rpm-ostree is available and
+    /// the deployment is versioned.
+    pub(crate) current_os_version: Option<String>,
+    /// OSTree commit checksum of the booted deployment, if rpm-ostree is available.
+    pub(crate) current_os_checksum: Option<String>,
+    /// Update stream tracked by the booted deployment, if any and if rpm-ostree is available.
+    pub(crate) stream: Option<String>,
+    /// OS version of the staged deployment, if an update is pending reboot.
+    pub(crate) pending_os_version: Option<String>,
+    /// Whether an update is staged, pending reboot (`"full"` level only).
+    pub(crate) pending_update: Option<bool>,
+    /// Instance type, if available on the platform (`"full"` level only).
+    pub(crate) instance_type: Option<String>,
+    /// Cloud region, if available on the platform (`"full"` level only).
+    pub(crate) region: Option<String>,
+    /// Cloud availability zone, if available on the platform (`"full"` level only).
+    pub(crate) availability_zone: Option<String>,
+    /// Seconds since boot (`"full"` level only).
+    pub(crate) uptime_seconds: Option<u64>,
+    /// Total system memory in MiB (`"full"` level only).
+    pub(crate) memory_mb: Option<u64>,
+    /// Total system memory in kB (`"full"` level only).
+    pub(crate) total_memory_kb: Option<u64>,
+    /// Number of online logical CPUs (`"full"` level only).
+    pub(crate) cpu_count: Option<usize>,
+    /// Release of the running kernel (`"full"` level only).
+    pub(crate) kernel_version: Option<String>,
+    /// Whether the kernel runs in FIPS mode (`"full"` level only).
+    pub(crate) fips_enabled: Option<bool>,
+    /// Type of the root filesystem (`"full"` level only).
+    pub(crate) root_fs_type: Option<String>,
+    /// Size of the root filesystem in GB, or of its backing filesystem for
+    /// overlay or bind-mounted roots (`"full"` level only).
+    pub(crate) root_disk_gb: Option<u64>,
+    /// Virtualization technology, if not running on bare metal (`"full"` level only).
+    pub(crate) virtualization: Option<String>,
+    /// Whether the machine runs in a VM or container, if known (`"full"` level only).
+    pub(crate) virtualized: Option<bool>,
+    /// SELinux mode: enforcing, permissive or disabled (`"full"` level only).
+    pub(crate) selinux_mode: Option<String>,
+    /// Firmware boot mode: uefi or bios (`"full"` level only).
+    pub(crate) firmware: Option<String>,
+    /// Whether Secure Boot is enabled, always `false` on non-UEFI systems (`"full"` level only).
+    pub(crate) secure_boot: Option<bool>,
+    /// Ignition spec version used at first boot, if recorded (`"full"` level only).
+    pub(crate) ignition_spec_version: Option<String>,
+    /// Sorted packages layered on the booted deployment, if rpm-ostree is
+    /// available (`"full"` level only).
+    pub(crate) layered_packages: Option<Vec<String>>,
+    /// Whether packages are layered on the booted deployment (`"full"` level only).
+    pub(crate) has_layered_packages: Option<bool>,
+    /// Kernel command-line arguments in the configured allowlist, if the
+    /// command-line is readable (`"full"` level only).
+    pub(crate) kernel_args: Option<BTreeMap<String, String>>,
+    /// Curated kernel flags, such as `fips`, if the command-line is readable
+    /// (`"full"` level only).
+    pub(crate) kernel_flags: Option<BTreeMap<String, String>>,
+    /// Salted hash of the machine ID, to deduplicate reports from the same machine.
+    pub(crate) machine_id_hash: Option<String>,
+    /// Time of the collection, as an RFC 3339 UTC timestamp.
+    pub(crate) collected_at: Option<String>,
+    /// Sequence number of the report, incremented on each report sent.
+    pub(crate) report_sequence: Option<u64>,
+    /// Keys to report, if restricted by the configuration.
+    #[serde(skip)]
+    pub(crate) report_fields: Option<Vec<String>>,
+    /// Keys not to report, taking precedence over `report_fields`.
+    #[serde(skip)]
+    pub(crate) exclude_fields: Vec<String>,
+}
+
+/// Typed report of the collected data: the identity, with the schema version.
+///
+/// Unlike `Identity::get_data`, this keeps absent values as `None` and
+/// numeric values as numbers.
+#[derive(Debug, Serialize)]
+pub(crate) struct Report<'a> {
+    /// Version of the report schema, always `SCHEMA_VERSION`.
+    pub(crate) schema_version: u32,
+    /// Collected data.
+    #[serde(flatten)]
+    pub(crate) identity: &'a Identity,
+}
+
+impl Identity {
+    /// Create from configuration.
+    ///
+    /// An unknown level is an error if `strict_level` is set, otherwise the
+    /// `"minimal"` level is used.
+    pub(crate) fn new(cfg: &inputs::CollectingInput) -> Result<Self, PingerError> {
+        Self::with_paths(cfg, &Paths::from_config(cfg))
+    }
+
+    /// Create from configuration, reading from the given paths instead of the
+    /// configured ones.
+    pub(crate) fn with_paths(
+        cfg: &inputs::CollectingInput,
+        paths: &Paths,
+    ) -> Result<Self, PingerError> {
+        let level = configured_level(cfg)?;
+        let id = Self::try_default(level, cfg, paths)?;
+
+        Ok(id)
+    }
+
+    /// Try to collect identity data at the given level, reading from the
+    /// given paths.
+    pub(crate) fn try_default(
+        level: CollectingLevel,
+        cfg: &inputs::CollectingInput,
+        paths: &Paths,
+    ) -> Result<Self, PingerError> {
+        let rpm_ostree_timeout = Duration::from_secs(cfg.rpm_ostree_timeout_secs);
+        let id = Self::try_from_paths(
+            level,
+            paths,
+            &rpm_ostree::Status::new(rpm_ostree_timeout),
+            &cfg.kernel_args,
+        )?;
+
+        Ok(Self {
+            instance_type: configured_granularity(cfg).apply(id.instance_type),
+            report_fields: cfg.report_fields.clone(),
+            exclude_fields: cfg.exclude_fields.clone().unwrap_or_default(),
+            ..id
+        })
+    }
+
+    /// Create from configuration, reading from files in the given directory
+    /// instead of the live system.
+    ///
+    /// Files are read relative to the directory as with `--root`, while the
+    /// output of rpm-ostree and `systemd-detect-virt` is read from its
+    /// `rpm-ostree-status.json` and `systemd-detect-virt` files, so that
+    /// nothing depends on the live system.
+    pub(crate) fn from_fixtures(
+        cfg: &inputs::CollectingInput,
+        dir: &Path,
+    ) -> Result<Self, PingerError> {
+        let level = configured_level(cfg)?;
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let status_path = path("rpm-ostree-status.json");
+        let virt_path = path("systemd-detect-virt");
+        let id = Self::try_from_paths_with(
+            level,
+            &Paths::default().with_root(dir),
+            &rpm_ostree::Status::with_runner(move || {
+                let status = std::fs::read(&status_path)
+                    .context(format!("failed to read '{}'", status_path))?;
+                Ok(status)
+            }),
+            &cfg.kernel_args,
+            || virtualization::detect_virt_from(&virt_path),
+            SystemTime::now,
+        )?;
+
+        Ok(Self {
+            instance_type: configured_granularity(cfg).apply(id.instance_type),
+            report_fields: cfg.report_fields.clone(),
+            exclude_fields: cfg.exclude_fields.clone().unwrap_or_default(),
+            ..id
+        })
+    }
+
+    /// Try to collect identity data at the given level, reading from the given paths.
+    ///
+    /// At the `"none"` (or `"off"`) level nothing is collected, no file is
+    /// read and rpm-ostree is not run.
+    fn try_from_paths(
+        level: CollectingLevel,
+        paths: &Paths,
+        rpm_ostree_status: &rpm_ostree::Status,
+        kernel_args_allowlist: &[String],
+    ) -> Result<Self, PingerError> {
+        Self::try_from_paths_with(
+            level,
+            paths,
+            rpm_ostree_status,
+            kernel_args_allowlist,
+            virtualization::detect_virt,
+            SystemTime::now,
+        )
+    }
+
+    /// Try to collect identity data as `try_from_paths`, detecting the
+    /// virtualization with `detect_virt` and timestamping the collection with
+    /// the given clock.
+    fn try_from_paths_with<V, F>(
+        level: CollectingLevel,
+        paths: &Paths,
+        rpm_ostree_status: &rpm_ostree::Status,
+        kernel_args_allowlist: &[String],
+        detect_virt: V,
+        clock: F,
+    ) -> Result<Self, PingerError>
+    where
+        V: FnOnce() -> Fallible<Option<String>>,
+        F: Fn() -> SystemTime,
+    {
+        if level == CollectingLevel::None {
+            let id = Self {
+                level,
+                ..Self::default()
+            };
+            return Ok(id);
+        }
+
+        let collected_at = Some(humantime::format_rfc3339_seconds(clock()).to_string());
+        let platform = platform::get_platform(&paths.kernel_args, Some(&paths.dmi_id_dir))?;
+        let architecture =
+            architecture_name(std::env::consts::ARCH, cfg!(target_endian = "little"));
+        let agent_version = AGENT_VERSION.to_string();
+        let (original_os_version, original_image_id) =
+            os_release::read_original(&paths.aleph_version)?;
+        // The original OS version is validated when read.
+        let original_major_version = original_os_version
+            .as_deref()
+            .and_then(|version| os_release::parse_os_version(version).ok())
+            .map(|version| version.major);
+        let (current_os_version, current_os_checksum, stream) = match rpm_ostree_status.booted() {
+            Ok(booted) => (
+                booted.version.clone(),
+                Some(booted.checksum.clone()),
+                booted.stream(),
+            ),
+            Err(e) => {
+                warn!("failed to query booted deployment: {}", e);
+                (None, None, None)
+            }
+        };
+        let pending_os_version = match rpm_ostree_status.staged() {
+            Ok(staged) => staged.and_then(|d| d.version.clone()),
+            Err(e) => {
+                warn!("failed to query staged deployment: {}", e);
+                None
+            }
+        };
+        let pending_update = match level {
+            // Failures were already reported above.
+            CollectingLevel::Full => rpm_ostree_status.staged().ok().map(|d| d.is_some()),
+            _ => None,
+        };
+
+        let instance_type = match level {
+            CollectingLevel::Full => match platform {
+                Platform::Aliyun | Platform::Aws | Platform::Azure | Platform::Gcp => or_warn(
+                    "instance type",
+                    instance_type::read_instance_type(&paths.afterburn_metadata, &platform),
+                )
+                .flatten(),
+                // No instance type in the metadata, which may not even exist,
+                // e.g. on DigitalOcean, PowerVS, VMware or Vultr.
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let (region, availability_zone) = match level {
+            CollectingLevel::Full => (
+                or_warn(
+                    "region",
+                    instance_type::read_region(&paths.afterburn_metadata, &platform),
+                )
+                .flatten(),
+                or_warn(
+                    "availability zone",
+                    instance_type::read_availability_zone(&paths.afterburn_metadata, &platform),
+                )
+                .flatten(),
+            ),
+            _ => (None, None),
+        };
+
+        let (uptime_seconds, total_memory_kb, cpu_count) = match level {
+            CollectingLevel::Full => (
+                or_warn("uptime", hardware::uptime_secs(&paths.uptime)),
+                or_warn("memory size", hardware::total_memory_kb(&paths.meminfo)),
+                or_warn("CPU count", hardware::cpu_count(&paths.cpu_online)),
+            ),
+            _ => (None, None, None),
+        };
+        let memory_mb = total_memory_kb.map(|kb| kb / 1024);
+
+        let kernel_version = match level {
+            CollectingLevel::Full => {
+                or_warn("kernel version", kernel::version(&paths.kernel_osrelease))
+            }
+            _ => None,
+        };
+
+        let fips_enabled = match level {
+            CollectingLevel::Full => {
+                or_warn("FIPS mode", kernel::fips_enabled(&paths.fips_enabled))
+            }
+            _ => None,
+        };
+
+        let root_fs_type = match level {
+            CollectingLevel::Full => or_warn(
+                "root filesystem type",
+                filesystem::root_fs_type(&paths.mounts),
+            ),
+            _ => None,
+        };
+
+        let root_disk_gb = match level {
+            CollectingLevel::Full => {
+                or_warn("root disk size", filesystem::disk_size_gb(&paths.root_dir))
+            }
+            _ => None,
+        };
+
+        let (virtualization, virtualized) = match level {
+            CollectingLevel::Full => {
+                let detected = detect_virt();
+                let virtualized =
+                    virtualization::is_virtualized(&detected, &paths.dmi_id_dir, &platform);
+                let virtualization = detected.unwrap_or_else(|e| {
+                    warn!("failed to detect virtualization: {}", e);
+                    None
+                });
+                (virtualization, virtualized)
+            }
+            _ => (None, None),
+        };
+
+        let selinux_mode = match level {
+            CollectingLevel::Full => {
+                or_warn("SELinux mode", selinux::selinux_mode(&paths.selinuxfs_dir))
+                    .map(|mode| mode.to_string())
+            }
+            _ => None,
+        };
+
+        let (firmware, secure_boot) = match level {
+            CollectingLevel::Full => (
+                Some(firmware::boot_mode(&paths.firmware_dir).to_string()),
+                match firmware::secure_boot_enabled(&paths.firmware_dir) {
+                    Ok(enabled) => Some(enabled),
+                    Err(e) => {
+                        warn!("failed to query Secure Boot state: {}", e);
+                        None
+                    }
+                },
+            ),
+            _ => (None, None),
+        };
+
+        let ignition_spec_version = match level {
+            CollectingLevel::Full => or_warn(
+                "Ignition spec version",
+                ignition::read_spec_version(&paths.ignition_result),
+            )
+            .flatten(),
+            _ => None,
+        };
+
+        let layered_packages = match level {
+            CollectingLevel::Full => match rpm_ostree_status.layered_packages() {
+                Ok(packages) => Some(packages),
+                Err(e) => {
+                    warn!("failed to query layered packages: {}", e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let kernel_args = match level {
+            CollectingLevel::Full => or_warn(
+                "kernel arguments",
+                kernel_args::read_kernel_args(&paths.kernel_args, kernel_args_allowlist),
+            ),
+            _ => None,
+        };
+
+        let kernel_flags = match level {
+            CollectingLevel::Full => or_warn(
+                "kernel flags",
+                kernel_args::read_kernel_flags(&paths.kernel_args),
+            ),
+            _ => None,
+        };
+
+        let has_layered_packages = match level {
+            CollectingLevel::Full => match rpm_ostree_status.has_layered_packages() {
+                Ok(layered) => Some(layered),
+                Err(e) => {
+                    warn!("failed to query layered packages: {}", e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let machine_id_hash =
+            match privacy::hashed_machine_id(&paths.machine_id, privacy::MACHINE_ID_SALT) {
+                Ok(hash) => Some(hash),
+                Err(e) => {
+                    warn!("failed to hash machine ID: {}", e);
+                    None
+                }
+            };
+
+        let id = Self {
+            level,
+            platform,
+            architecture,
+            agent_version,
+            original_os_version,
+            original_major_version,
+            original_image_id,
+            current_os_version,
+            current_os_checksum,
+            stream,
+            pending_os_version,
+            pending_update,
+            instance_type,
+            region,
+            availability_zone,
+            uptime_seconds,
+            memory_mb,
+            total_memory_kb,
+            cpu_count,
+            kernel_version,
+            fips_enabled,
+            root_fs_type,
+            root_disk_gb,
+            virtualization,
+            virtualized,
+            selinux_mode,
+            firmware,
+            secure_boot,
+            ignition_spec_version,
+            layered_packages,
+            has_layered_packages,
+            kernel_args,
+            kernel_flags,
+            machine_id_hash,
+            collected_at,
+            report_sequence: None,
+            report_fields: None,
+            exclude_fields: vec![],
+        };
+
+        Ok(id)
+    }
+
+    /// Return the collected data as a typed report.
+    pub(crate) fn to_report(&self) -> Report<'_> {
+        Report {
+            schema_version: SCHEMA_VERSION,
+            identity: self,
+        }
+    }
+
+    /// Serialize the collected data to a JSON value.
+    ///
+    /// This is the preferred wire format: unlike `get_data`, absent values
+    /// are kept as `null` and numeric values as numbers. The schema version
+    /// is always included.
+    pub(crate) fn to_json_value(&self) -> Fallible<serde_json::Value> {
+        let mut value = serde_json::to_value(self.to_report())
+            .context("failed to serialize identity to JSON")?;
+        if let serde_json::Value::Object(fields) = &mut value {
+            fields.retain(|name, _| name == "schema_version" || self.reports_field(name));
+        }
+        Ok(value)
+    }
+
+    /// Serialize the collected data to a JSON document.
+    pub fn to_json(&self) -> Fallible<String> {
+        let json = serde_json::to_string(&self.to_json_value()?)?;
+        Ok(json)
+    }
+
+    /// Serialize the collected data to a document in the given format.
+    pub(crate) fn serialize(&self, format: Format) -> Fallible<Vec<u8>> {
+        let document = match format {
+            Format::Json => self.to_json()?.into_bytes(),
+            Format::MessagePack => rmp_serde::to_vec_named(&self.to_json_value()?)
+                .context("failed to serialize identity to MessagePack")?,
+        };
+        Ok(document)
+    }
+
+    /// Serialize the collected data to a pretty-printed JSON document.
+    pub(crate) fn to_json_pretty(&self) -> Fallible<String> {
+        let json = serde_json::to_string_pretty(&self.to_json_value()?)?;
+        Ok(json)
+    }
+
+    /// Return the collected data as key-value pairs.
+    ///
+    /// Absent values are omitted, as are fields whose minimum level is above
+    /// the collecting level. At the `"none"` level, no data is returned.
+    pub fn get_data(&self) -> HashMap<String, String> {
+        use CollectingLevel::{Full, Minimal};
+
+        let level = self.level;
+        let fields = vec![
+            Field::new("level", Minimal, Some(self.level.to_string())),
+            Field::new("platform", Minimal, Some(self.platform.to_string())),
+            Field::new("architecture", Minimal, Some(self.architecture.clone())),
+            Field::new("agent_version", Minimal, Some(self.agent_version.clone())),
+            Field::new(
+                "original_os_version",
+                Minimal,
+                self.original_os_version.clone(),
+            ),
+            Field::new(
+                "original_major_version",
+                Minimal,
+                self.original_major_version.map(|v| v.to_string()),
+            ),
+            Field::new("original_image_id", Minimal, self.original_image_id.clone()),
+            Field::new(
+                "current_os_version",
+                Minimal,
+                self.current_os_version.clone(),
+            ),
+            Field::new(
+                "current_os_checksum",
+                Minimal,
+                self.current_os_checksum.clone(),
+            ),
+            Field::new("stream", Minimal, self.stream.clone()),
+            Field::new(
+                "pending_os_version",
+                Minimal,
+                self.pending_os_version.clone(),
+            ),
+            Field::new("machine_id_hash", Minimal, self.machine_id_hash.clone()),
+            Field::new(
+                "report_sequence",
+                Minimal,
+                self.report_sequence.map(|v| v.to_string()),
+            ),
+            Field::new(
+                "pending_update",
+                Full,
+                self.pending_update.map(|v| v.to_string()),
+            ),
+            Field::new("instance_type", Full, self.instance_type.clone()),
+            Field::new("region", Full, self.region.clone()),
+            Field::new("availability_zone", Full, self.availability_zone.clone()),
+            Field::new(
+                "uptime_seconds",
+                Full,
+                self.uptime_seconds.map(|v| v.to_string()),
+            ),
+            Field::new("memory_mb", Full, self.memory_mb.map(|v| v.to_string())),
+            Field::new(
+                "total_memory_kb",
+                Full,
+                self.total_memory_kb.map(|v| v.to_string()),
+            ),
+            Field::new("cpu_count", Full, self.cpu_count.map(|v| v.to_string())),
+            Field::new("kernel_version", Full, self.kernel_version.clone()),
+            Field::new(
+                "fips_enabled",
+                Full,
+                self.fips_enabled.map(|v| v.to_string()),
+            ),
+            Field::new("root_fs_type", Full, self.root_fs_type.clone()),
+            Field::new(
+                "root_disk_gb",
+                Full,
+                self.root_disk_gb.map(|v| v.to_string()),
+            ),
+            Field::new("virtualization", Full, self.virtualization.clone()),
+            Field::new("virtualized", Full, self.virtualized.map(|v| v.to_string())),
+            Field::new("selinux_mode", Full, self.selinux_mode.clone()),
+            Field::new("firmware", Full, self.firmware.clone()),
+            Field::new("secure_boot", Full, self.secure_boot.map(|v| v.to_string())),
+            Field::new(
+                "ignition_spec_version",
+                Full,
+                self.ignition_spec_version.clone(),
+            ),
+            Field::new(
+                "layered_packages",
+                Full,
+                non_empty(&self.layered_packages).map(|packages| packages.join(",")),
+            ),
+            Field::new(
+                "has_layered_packages",
+                Full,
+                self.has_layered_packages.map(|v| v.to_string()),
+            ),
+            Field::new(
+                "kernel_args",
+                Full,
+                non_empty(&self.kernel_args).map(join_kernel_args),
+            ),
+            Field::new(
+                "kernel_flags",
+                Full,
+                non_empty(&self.kernel_flags).map(join_kernel_args),
+            ),
+        ];
+
+        fields
+            .into_iter()
+            .filter(|field| level >= field.min_level && self.reports_field(field.name))
+            .filter_map(|field| Some((field.name.to_string(), field.value?)))
+            .collect()
+    }
+
+    /// Whether the given key is reported, according to `report_fields` and
+    /// `exclude_fields`.
+    ///
+    /// An empty `report_fields` list reports all fields, like an absent one.
+    fn reports_field(&self, name: &str) -> bool {
+        if self.exclude_fields.iter().any(|field| field == name) {
+            return false;
+        }
+        match &self.report_fields {
+            Some(fields) if !fields.is_empty() => fields.iter().any(|field| field == name),
+            _ => true,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn mock_default(level: &str) -> Self {
+        let level = CollectingLevel::parse(level).expect("invalid mock level");
+        let mut id = Self {
+            level,
+            platform: Platform::from_id("mock-platform"),
+            architecture: "x86_64".to_string(),
+            agent_version: AGENT_VERSION.to_string(),
+            original_os_version: Some("30.20190923.dev.2-2".to_string()),
+            original_major_version: Some(30),
+            current_os_version: Some("30.20190923.dev.2-2".to_string()),
+            current_os_checksum: Some(
+                "4b8d4e5a0f7e8a1a5f2c3e1f0b6b4a8e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a".to_string(),
+            ),
+            stream: Some("testing".to_string()),
+            machine_id_hash: Some(
+                "ed2a68d508fddc8fa406a9a242392c18101e1058467b6e87651854bb4f92e4c2".to_string(),
+            ),
+            collected_at: Some("2019-10-02T12:00:00Z".to_string()),
+            ..Self::default()
+        };
+
+        if level == CollectingLevel::Full {
+            id.pending_update = Some(false);
+            id.instance_type = Some("mock-instance-type".to_string());
+            id.region = Some("mock-region".to_string());
+            id.availability_zone = Some("mock-zone".to_string());
+            id.uptime_seconds = Some(12346);
+            id.memory_mb = Some(3931);
+            id.total_memory_kb = Some(4_026_348);
+            id.cpu_count = Some(4);
+            id.kernel_version = Some("5.3.7-301.fc31.x86_64".to_string());
+            id.fips_enabled = Some(false);
+            id.root_fs_type = Some("xfs".to_string());
+            id.root_disk_gb = Some(16);
+            id.virtualization = Some("kvm".to_string());
+            id.virtualized = Some(true);
+            id.selinux_mode = Some("enforcing".to_string());
+            id.firmware = Some("uefi".to_string());
+            id.secure_boot = Some(true);
+            id.ignition_spec_version = Some("3.3.0".to_string());
+            id.layered_packages = Some(vec!["htop".to_string(), "vim-enhanced".to_string()]);
+            id.has_layered_packages = Some(true);
+            id.kernel_args = Some(
+                vec![("mitigations", "auto,nosmt"), ("rw", "")]
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            );
+            id.kernel_flags = Some(
+                vec![("fips".to_string(), "1".to_string())]
+                    .into_iter()
+                    .collect(),
+            );
+        }
+
+        id
+    }
+}
+
+/// Return the collection, unless it is absent or empty, so that empty
+/// collections are omitted from the key-value pairs like absent values.
+fn non_empty<C>(collection: &Option<C>) -> Option<&C>
+where
+    for<'a> &'a C: IntoIterator,
+{
+    collection
+        .as_ref()
+        .filter(|c| c.into_iter().next().is_some())
+}
+
+/// Join kernel arguments back into command-line form.
+fn join_kernel_args(args: &BTreeMap<String, String>) -> String {
+    let args: Vec<String> = args
+        .iter()
+        .map(|(key, value)| match value.as_str() {
+            "" => key.clone(),
+            _ => format!("{}={}", key, value),
+        })
+        .collect();
+    args.join(" ")
+}
+
+/// Return the names of all the reportable keys.
+pub(crate) fn field_names() -> Vec<String> {
+    match Identity::default().to_json_value() {
+        Ok(serde_json::Value::Object(fields)) => fields.keys().cloned().collect(),
+        _ => vec![],
+    }
+}
+
+/// Collected field, with the minimum level at which it is reported.
+struct Field {
+    name: &'static str,
+    min_level: CollectingLevel,
+    value: Option<String>,
+}
+
+impl Field {
+    fn new(name: &'static str, min_level: CollectingLevel, value: Option<String>) -> Self {
+        Self {
+            name,
+            min_level,
+            value,
+        }
+    }
+}
+
+/// Return the configured collecting level.
+///
+/// Unknown levels fail with `PingerError::InvalidLevel` in strict mode, and
+/// fall back to `"minimal"` otherwise.
+fn configured_level(cfg: &inputs::CollectingInput) -> Result<CollectingLevel, PingerError> {
+    match CollectingLevel::parse(&cfg.level) {
+        Some(level) => Ok(level),
+        None if cfg.strict_level => {
+            let err = inputs::InvalidLevelError {
+                level: cfg.level.clone(),
+            };
+            Err(err.into())
+        }
+        None => {
+            warn!(
+                "unknown collecting level '{}', collecting at 'minimal' level",
+                cfg.level
+            );
+            Ok(CollectingLevel::Minimal)
+        }
+    }
+}
+
+/// Return the collected value, or `None` with a warning if it could not be
+/// collected, so that one unreadable source does not fail the whole identity.
+fn or_warn<T>(what: &str, collected: Fallible<T>) -> Option<T> {
+    match collected {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("failed to collect {}: {}", what, e);
+            None
+        }
+    }
+}
+
+/// Return the configured instance type granularity.
+///
+/// Unknown granularities drop the instance type.
+fn configured_granularity(cfg: &inputs::CollectingInput) -> InstanceTypeGranularity {
+    InstanceTypeGranularity::parse(&cfg.instance_type_granularity).unwrap_or_else(|| {
+        warn!(
+            "unknown instance type granularity '{}', not reporting instance type",
+            cfg.instance_type_granularity
+        );
+        InstanceTypeGranularity::None
+    })
+}
+
+/// Map a Rust target architecture to the name used by Fedora CoreOS artifacts.
+fn architecture_name(arch: &str, little_endian: bool) -> String {
+    match arch {
+        "powerpc64" if little_endian => "ppc64le".to_string(),
+        _ => arch.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    /// Create a root directory holding the given kernel command-line, aleph
+    /// version and Afterburn metadata fixtures, plus hardware information
+    /// for the `"full"` level.
+    fn fixture_root(cmdline: &str, aleph: &str, afterburn: &str) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let paths = Paths::default().with_root(root.path());
+        let fixtures = vec![
+            (cmdline, &paths.kernel_args),
+            (aleph, &paths.aleph_version),
+            (afterburn, &paths.afterburn_metadata),
+            ("proc-uptime", &paths.uptime),
+            ("proc-meminfo", &paths.meminfo),
+            ("cpu-online", &paths.cpu_online),
+            ("kernel-osrelease", &paths.kernel_osrelease),
+            ("proc-mounts-xfs", &paths.mounts),
+            ("ignition-result.json", &paths.ignition_result),
+            ("machine-id", &paths.machine_id),
+        ];
+        for (fixture, dest) in fixtures {
+            std::fs::create_dir_all(Path::new(dest).parent().unwrap()).unwrap();
+            std::fs::copy(Path::new("tests/fixtures").join(fixture), dest).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_minimal() {
+        let id = Identity::mock_default("minimal");
+        let vars = id.get_data();
+
+        assert_eq!(vars.get("level"), Some(&"minimal".to_string()));
+        assert!(vars.contains_key("platform"));
+        assert_eq!(vars.get("architecture"), Some(&"x86_64".to_string()));
+        assert!(vars.contains_key("original_os_version"));
+        assert_eq!(vars.get("original_major_version"), Some(&"30".to_string()));
+        assert!(vars.contains_key("current_os_version"));
+        assert_eq!(vars.get("current_os_checksum").map(String::len), Some(64));
+        assert_eq!(vars.get("stream"), Some(&"testing".to_string()));
+        assert!(!vars.contains_key("pending_os_version"));
+        assert!(!vars.contains_key("pending_update"));
+        assert!(!vars.contains_key("instance_type"));
+        assert!(!vars.contains_key("region"));
+        assert!(!vars.contains_key("availability_zone"));
+        assert!(!vars.contains_key("uptime_seconds"));
+        assert!(!vars.contains_key("memory_mb"));
+        assert!(!vars.contains_key("total_memory_kb"));
+        assert!(!vars.contains_key("cpu_count"));
+        assert!(!vars.contains_key("kernel_version"));
+        assert!(!vars.contains_key("fips_enabled"));
+        assert!(!vars.contains_key("root_fs_type"));
+        assert!(!vars.contains_key("root_disk_gb"));
+        assert!(!vars.contains_key("virtualization"));
+        assert!(!vars.contains_key("selinux_mode"));
+        assert!(!vars.contains_key("firmware"));
+        assert!(!vars.contains_key("secure_boot"));
+        assert!(!vars.contains_key("ignition_spec_version"));
+        assert!(!vars.contains_key("layered_packages"));
+        assert!(!vars.contains_key("has_layered_packages"));
+        assert!(!vars.contains_key("kernel_args"));
+        assert!(!vars.contains_key("kernel_flags"));
+        assert_eq!(vars.get("machine_id_hash").map(String::len), Some(64));
+    }
+
+    #[test]
+    fn test_full() {
+        let id = Identity::mock_default("full");
+        let vars = id.get_data();
+
+        assert_eq!(vars.get("level"), Some(&"full".to_string()));
+        assert!(vars.contains_key("platform"));
+        assert_eq!(vars.get("architecture"), Some(&"x86_64".to_string()));
+        assert!(vars.contains_key("original_os_version"));
+        assert_eq!(vars.get("original_major_version"), Some(&"30".to_string()));
+        assert!(vars.contains_key("current_os_version"));
+        assert_eq!(vars.get("current_os_checksum").map(String::len), Some(64));
+        assert_eq!(vars.get("stream"), Some(&"testing".to_string()));
+        assert!(!vars.contains_key("pending_os_version"));
+        assert_eq!(vars.get("pending_update"), Some(&"false".to_string()));
+        assert!(vars.contains_key("instance_type"));
+        assert!(vars.contains_key("region"));
+        assert!(vars.contains_key("availability_zone"));
+        assert_eq!(vars.get("uptime_seconds"), Some(&"12346".to_string()));
+        assert_eq!(vars.get("memory_mb"), Some(&"3931".to_string()));
+        assert_eq!(vars.get("total_memory_kb"), Some(&"4026348".to_string()));
+        assert_eq!(vars.get("cpu_count"), Some(&"4".to_string()));
+        assert_eq!(
+            vars.get("kernel_version"),
+            Some(&"5.3.7-301.fc31.x86_64".to_string())
+        );
+        assert_eq!(vars.get("fips_enabled"), Some(&"false".to_string()));
+        assert_eq!(vars.get("root_fs_type"), Some(&"xfs".to_string()));
+        assert_eq!(vars.get("root_disk_gb"), Some(&"16".to_string()));
+        assert_eq!(vars.get("virtualization"), Some(&"kvm".to_string()));
+        assert_eq!(vars.get("selinux_mode"), Some(&"enforcing".to_string()));
+        assert_eq!(vars.get("firmware"), Some(&"uefi".to_string()));
+        assert_eq!(vars.get("secure_boot"), Some(&"true".to_string()));
+        assert_eq!(
+            vars.get("ignition_spec_version"),
+            Some(&"3.3.0".to_string())
+        );
+        assert_eq!(
+            vars.get("layered_packages"),
+            Some(&"htop,vim-enhanced".to_string())
+        );
+        assert_eq!(
+            vars.get("has_layered_packages"),
+            Some(&"true".to_string())
+        );
+        assert_eq!(
+            vars.get("kernel_args"),
+            Some(&"mitigations=auto,nosmt rw".to_string())
+        );
+        assert_eq!(vars.get("kernel_flags"), Some(&"fips=1".to_string()));
+        assert_eq!(vars.get("machine_id_hash").map(String::len), Some(64));
+    }
+
+    #[test]
+    fn test_full_superset_of_minimal() {
+        let minimal = Identity::mock_default("minimal").get_data();
+        let full = Identity::mock_default("full").get_data();
+
+        for key in minimal.keys() {
+            assert!(full.contains_key(key), "missing key at full level: {}", key);
+        }
+        assert!(full.len() > minimal.len());
+    }
+
+    #[test]
+    fn test_empty_collections() {
+        // Empty collections are omitted, not reported as empty strings.
+        let id = Identity {
+            layered_packages: Some(vec![]),
+            kernel_args: Some(BTreeMap::new()),
+            kernel_flags: Some(BTreeMap::new()),
+            ..Identity::mock_default("full")
+        };
+        let vars = id.get_data();
+        assert!(!vars.contains_key("layered_packages"));
+        assert!(!vars.contains_key("kernel_args"));
+        assert!(!vars.contains_key("kernel_flags"));
+
+        // They are still reported as collected in JSON.
+        let value = id.to_json_value().unwrap();
+        assert_eq!(value["layered_packages"], serde_json::json!([]));
+        assert_eq!(value["kernel_args"], serde_json::json!({}));
+        assert_eq!(
+            Identity::mock_default("minimal").to_json_value().unwrap()["kernel_args"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn test_field_min_level() {
+        // Values collected at the full level are not reported at a lower level.
+        let id = Identity {
+            level: CollectingLevel::Minimal,
+            ..Identity::mock_default("full")
+        };
+        let vars = id.get_data();
+
+        let mut keys: Vec<&String> = vars.keys().collect();
+        keys.sort();
+        let minimal = Identity::mock_default("minimal").get_data();
+        let mut expected: Vec<&String> = minimal.keys().collect();
+        expected.sort();
+        assert_eq!(keys, expected);
+        assert!(!vars.contains_key("instance_type"));
+        assert!(!vars.contains_key("kernel_args"));
+    }
+
+    #[test]
+    fn test_report_fields() {
+        let id = Identity {
+            report_fields: Some(vec!["platform".to_string(), "architecture".to_string()]),
+            ..Identity::mock_default("full")
+        };
+
+        let vars = id.get_data();
+        let mut keys: Vec<&str> = vars.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["architecture", "platform"]);
+
+        let value = id.to_json_value().unwrap();
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["architecture", "platform", "schema_version"]);
+
+        // Listed fields are still restricted by the level.
+        let id = Identity {
+            report_fields: Some(vec!["platform".to_string(), "instance_type".to_string()]),
+            ..Identity::mock_default("minimal")
+        };
+        let keys: Vec<String> = id.get_data().keys().cloned().collect();
+        assert_eq!(keys, vec!["platform"]);
+
+        // An empty list reports all fields.
+        let id = Identity {
+            report_fields: Some(vec![]),
+            ..Identity::mock_default("full")
+        };
+        assert_eq!(id.get_data(), Identity::mock_default("full").get_data());
+    }
+
+    #[test]
+    fn test_exclude_fields() {
+        let full = Identity::mock_default("full");
+        let id = Identity {
+            exclude_fields: vec!["instance_type".to_string()],
+            ..Identity::mock_default("full")
+        };
+
+        let vars = id.get_data();
+        assert!(!vars.contains_key("instance_type"));
+        assert_eq!(vars.len(), full.get_data().len() - 1);
+        assert!(vars.contains_key("region"));
+        assert!(vars.contains_key("platform"));
+
+        let value = id.to_json_value().unwrap();
+        assert!(value.get("instance_type").is_none());
+        assert_eq!(value["region"], "mock-region");
+
+        // Exclusion wins over an explicit listing.
+        let id = Identity {
+            report_fields: Some(vec!["platform".to_string(), "instance_type".to_string()]),
+            ..id
+        };
+        let keys: Vec<String> = id.get_data().keys().cloned().collect();
+        assert_eq!(keys, vec!["platform"]);
+    }
+
+    #[test]
+    fn test_field_names() {
+        let names = field_names();
+        assert!(names.contains(&"platform".to_string()));
+        assert!(names.contains(&"kernel_args".to_string()));
+        assert!(!names.contains(&"report_fields".to_string()));
+
+        let vars = Identity::mock_default("full").get_data();
+        for key in vars.keys() {
+            assert!(names.contains(key), "unknown key: {}", key);
+        }
+    }
+
+    #[test]
+    fn test_full_missing_instance_type() {
+        let id = Identity {
+            instance_type: None,
+            ..Identity::mock_default("full")
+        };
+        let vars = id.get_data();
+
+        assert!(!vars.contains_key("instance_type"));
+        assert!(vars.contains_key("platform"));
+    }
+
+    #[test]
+    fn test_full_unreadable_sources() {
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        let paths = Paths::default().with_root(root.path());
+        std::fs::remove_file(&paths.uptime).unwrap();
+        std::fs::remove_file(&paths.meminfo).unwrap();
+        std::fs::write(&paths.cpu_online, "").unwrap();
+        std::fs::remove_file(&paths.mounts).unwrap();
+        std::fs::remove_file(&paths.kernel_osrelease).unwrap();
+        std::fs::create_dir_all(Path::new(&paths.fips_enabled).parent().unwrap()).unwrap();
+        std::fs::write(&paths.fips_enabled, "garbage\n").unwrap();
+        std::fs::write(&paths.ignition_result, "garbage\n").unwrap();
+        std::fs::remove_file(&paths.afterburn_metadata).unwrap();
+        std::fs::create_dir_all(&paths.selinuxfs_dir).unwrap();
+        std::fs::write(Path::new(&paths.selinuxfs_dir).join("enforce"), "").unwrap();
+
+        let status = rpm_ostree::Status::with_runner(|| {
+            Ok(std::fs::read("tests/fixtures/rpm-ostree-status.json")?)
+        });
+        let id = Identity::try_from_paths(CollectingLevel::Full, &paths, &status, &[]).unwrap();
+        assert_eq!(id.platform, "aws");
+        assert_eq!(id.uptime_seconds, None);
+        assert_eq!(id.memory_mb, None);
+        assert_eq!(id.total_memory_kb, None);
+        assert_eq!(id.cpu_count, None);
+        assert_eq!(id.selinux_mode, None);
+        assert_eq!(id.root_fs_type, None);
+        assert_eq!(id.kernel_version, None);
+        assert_eq!(id.fips_enabled, None);
+        assert_eq!(id.ignition_spec_version, None);
+        assert_eq!(id.instance_type, None);
+        assert_eq!(id.region, None);
+        assert_eq!(id.availability_zone, None);
+    }
+
+    #[test]
+    fn test_full_unreadable_cmdline() {
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        let paths = Paths::default().with_root(root.path());
+        std::fs::remove_file(&paths.kernel_args).unwrap();
+        std::fs::create_dir_all(&paths.dmi_id_dir).unwrap();
+        for attr in &["product_name", "sys_vendor"] {
+            let fixture = Path::new("tests/fixtures/dmi-vmware").join(attr);
+            std::fs::copy(fixture, Path::new(&paths.dmi_id_dir).join(attr)).unwrap();
+        }
+
+        let status = rpm_ostree::Status::with_runner(|| failure::bail!("rpm-ostree not found"));
+        let allowlist = vec!["mitigations".to_string()];
+        let id = Identity::try_from_paths(CollectingLevel::Full, &paths, &status, &allowlist)
+            .unwrap();
+        assert_eq!(id.platform, Platform::VMware);
+        assert_eq!(id.kernel_args, None);
+        assert_eq!(id.kernel_flags, None);
+        assert!(!id.get_data().contains_key("kernel_args"));
+    }
+
+    #[test]
+    fn test_architecture_name() {
+        let tests = vec![
+            ("x86_64", true, "x86_64"),
+            ("aarch64", true, "aarch64"),
+            ("powerpc64", true, "ppc64le"),
+            ("powerpc64", false, "powerpc64"),
+            ("s390x", false, "s390x"),
+        ];
+        for (arch, little_endian, expected) in tests {
+            assert_eq!(architecture_name(arch, little_endian), expected);
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let id = Identity::mock_default("full");
+        let report = id.to_report();
+        assert_eq!(report.schema_version, SCHEMA_VERSION);
+        assert_eq!(report.identity.level, CollectingLevel::Full);
+
+        // Every identity field is serialized, next to the schema version.
+        let value = serde_json::to_value(&report).unwrap();
+        let fields = value.as_object().unwrap();
+        assert_eq!(fields["schema_version"], SCHEMA_VERSION);
+        let serialized = serde_json::to_value(&id).unwrap();
+        for (name, value) in serialized.as_object().unwrap() {
+            assert_eq!(&fields[name], value, "{}", name);
+        }
+        assert_eq!(fields.len(), serialized.as_object().unwrap().len() + 1);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = Identity::mock_default("minimal").to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value["level"], "minimal");
+        assert_eq!(value["instance_type"], serde_json::Value::Null);
+        assert_eq!(value["cpu_count"], serde_json::Value::Null);
+
+        let json = Identity::mock_default("full").to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value["instance_type"], "mock-instance-type");
+        assert_eq!(value["cpu_count"], 4);
+    }
+
+    #[test]
+    fn test_serialize() {
+        for level in &["minimal", "full"] {
+            let id = Identity::mock_default(level);
+            let expected = id.to_json_value().unwrap();
+
+            let json = id.serialize(Format::Json).unwrap();
+            let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+            assert_eq!(value, expected);
+
+            let msgpack = id.serialize(Format::MessagePack).unwrap();
+            let value: serde_json::Value = rmp_serde::from_slice(&msgpack).unwrap();
+            assert_eq!(value, expected);
+        }
+    }
+
+    #[test]
+    fn test_to_json_pretty() {
+        let id = Identity::mock_default("minimal");
+        let json = id.to_json_pretty().unwrap();
+        assert!(json.lines().count() > 1);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value, id.to_json_value().unwrap());
+    }
+
+    #[test]
+    fn test_to_json_value_schema() {
+        let value = Identity::mock_default("full").to_json_value().unwrap();
+        let expected = serde_json::json!({
+            "schema_version": 10,
+            "level": "full",
+            "platform": "mock-platform",
+            "architecture": "x86_64",
+            "agent_version": AGENT_VERSION,
+            "original_os_version": "30.20190923.dev.2-2",
+            "original_major_version": 30,
+            "original_image_id": null,
+            "current_os_version": "30.20190923.dev.2-2",
+            "current_os_checksum": "4b8d4e5a0f7e8a1a5f2c3e1f0b6b4a8e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a",
+            "stream": "testing",
+            "pending_os_version": null,
+            "pending_update": false,
+            "instance_type": "mock-instance-type",
+            "region": "mock-region",
+            "availability_zone": "mock-zone",
+            "uptime_seconds": 12346,
+            "memory_mb": 3931,
+            "total_memory_kb": 4026348,
+            "cpu_count": 4,
+            "kernel_version": "5.3.7-301.fc31.x86_64",
+            "fips_enabled": false,
+            "root_fs_type": "xfs",
+            "root_disk_gb": 16,
+            "virtualization": "kvm",
+            "virtualized": true,
+            "selinux_mode": "enforcing",
+            "firmware": "uefi",
+            "secure_boot": true,
+            "ignition_spec_version": "3.3.0",
+            "layered_packages": ["htop", "vim-enhanced"],
+            "has_layered_packages": true,
+            "kernel_args": {"mitigations": "auto,nosmt", "rw": ""},
+            "kernel_flags": {"fips": "1"},
+            "machine_id_hash": "ed2a68d508fddc8fa406a9a242392c18101e1058467b6e87651854bb4f92e4c2",
+            "collected_at": "2019-10-02T12:00:00Z",
+            "report_sequence": null,
+        });
+
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn test_schema_version() {
+        for level in &["minimal", "full"] {
+            let value = Identity::mock_default(level).to_json_value().unwrap();
+            assert_eq!(value["schema_version"], SCHEMA_VERSION, "level: {}", level);
+        }
+
+        let id = Identity {
+            report_fields: Some(vec!["platform".to_string()]),
+            exclude_fields: vec!["schema_version".to_string()],
+            ..Identity::mock_default("minimal")
+        };
+        let value = id.to_json_value().unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"schema_version": SCHEMA_VERSION, "platform": "mock-platform"})
+        );
+    }
+
+    #[test]
+    fn test_off() {
+        let id = Identity::mock_default("off");
+        assert!(id.get_data().is_empty());
+    }
+
+    #[test]
+    fn test_rpm_ostree_unavailable() {
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        let id = Identity::try_from_paths(
+            CollectingLevel::Full,
+            &Paths::default().with_root(root.path()),
+            &rpm_ostree::Status::with_command("/nonexistent/rpm-ostree", Duration::from_secs(5)),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(id.platform, "aws");
+        assert_eq!(id.original_os_version, Some("30.20190905.0".to_string()));
+        assert_eq!(id.original_major_version, Some(30));
+        assert_eq!(id.instance_type, Some("m5.large".to_string()));
+        assert_eq!(id.region, Some("us-east-1".to_string()));
+        assert_eq!(id.availability_zone, Some("us-east-1c".to_string()));
+        assert_eq!(id.current_os_version, None);
+        assert_eq!(id.current_os_checksum, None);
+
+        let vars = id.get_data();
+        assert!(!vars.contains_key("current_os_version"));
+        assert!(!vars.contains_key("current_os_checksum"));
+        assert!(!vars.contains_key("stream"));
+        assert_eq!(id.stream, None);
+    }
+
+    #[test]
+    fn test_from_fixtures() {
+        let cfg = inputs::CollectingInput {
+            level: "minimal".to_string(),
+            ..inputs::CollectingInput::default()
+        };
+        let id = Identity::from_fixtures(&cfg, Path::new("tests/fixtures/identity")).unwrap();
+
+        assert_eq!(id.level, CollectingLevel::Minimal);
+        assert_eq!(id.platform, "aws");
+        assert_eq!(id.original_os_version, Some("30.20190905.0".to_string()));
+        assert_eq!(id.original_major_version, Some(30));
+        assert_eq!(
+            id.current_os_version,
+            Some("30.20190923.dev.2-2".to_string())
+        );
+        assert_eq!(id.stream, Some("testing".to_string()));
+        assert_eq!(id.pending_os_version, Some("30.20191002.0".to_string()));
+        assert_eq!(id.instance_type, None);
+
+        let cfg = inputs::CollectingInput {
+            level: "full".to_string(),
+            ..cfg
+        };
+        let id = Identity::from_fixtures(&cfg, Path::new("tests/fixtures/identity")).unwrap();
+        assert_eq!(id.instance_type, Some("m5.large".to_string()));
+        assert_eq!(id.region, Some("us-east-1".to_string()));
+        assert_eq!(id.pending_update, Some(true));
+        assert_eq!(id.uptime_seconds, Some(12346));
+        assert_eq!(id.cpu_count, Some(4));
+        assert_eq!(id.kernel_version, Some("5.3.7-301.fc31.x86_64".to_string()));
+        assert_eq!(id.root_fs_type, Some("xfs".to_string()));
+        assert_eq!(id.virtualization, Some("kvm".to_string()));
+        assert_eq!(id.virtualized, Some(true));
+
+        match Identity::from_fixtures(&cfg, Path::new("/nonexistent")).unwrap_err() {
+            PingerError::PlatformDetection(e) => assert!(
+                e.to_string().contains("(/nonexistent/proc/cmdline)"),
+                "{}",
+                e
+            ),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_instance_type_granularity() {
+        let tests = vec![
+            ("full", Some("m5.large".to_string())),
+            ("family", Some("m5".to_string())),
+            ("none", None),
+            ("unknown", None),
+        ];
+        for (granularity, expected) in tests {
+            let cfg = inputs::CollectingInput {
+                level: "full".to_string(),
+                instance_type_granularity: granularity.to_string(),
+                ..inputs::CollectingInput::default()
+            };
+            let id = Identity::from_fixtures(&cfg, Path::new("tests/fixtures/identity")).unwrap();
+            assert_eq!(id.instance_type, expected, "failed testcase: '{}'", granularity);
+        }
+    }
+
+    #[test]
+    fn test_configured_paths() {
+        let cfg = inputs::CollectingInput {
+            level: "minimal".to_string(),
+            kernel_args_path: "tests/fixtures/cmdline-aws".to_string(),
+            aleph_version_path: "tests/fixtures/aleph-version-new.json".to_string(),
+            afterburn_metadata_path: "tests/fixtures/afterburn-aws".to_string(),
+            ..inputs::CollectingInput::default()
+        };
+        let status = rpm_ostree::Status::with_runner(|| {
+            Ok(std::fs::read("tests/fixtures/rpm-ostree-status.json")?)
+        });
+        let collect = |cfg: &inputs::CollectingInput| {
+            let paths = Paths::from_config(cfg);
+            Identity::try_from_paths(CollectingLevel::Minimal, &paths, &status, &[])
+        };
+        let id = collect(&cfg).unwrap();
+
+        assert_eq!(id.platform, "aws");
+        assert_eq!(id.original_os_version, Some("36.20220618.3.1".to_string()));
+
+        let missing = inputs::CollectingInput {
+            aleph_version_path: "/nonexistent/aleph-version.json".to_string(),
+            ..cfg.clone()
+        };
+        let id = collect(&missing).unwrap();
+        assert_eq!(id.original_os_version, None);
+
+        let malformed = inputs::CollectingInput {
+            aleph_version_path: "tests/fixtures/aleph-version-malformed.json".to_string(),
+            ..cfg
+        };
+        match collect(&malformed).unwrap_err() {
+            PingerError::Aleph(AlephError::MetadataParse { path, .. }) => {
+                assert_eq!(path, "tests/fixtures/aleph-version-malformed.json")
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_paths() {
+        let cfg = inputs::CollectingInput {
+            kernel_args_path: "/run/cmdline".to_string(),
+            ..inputs::CollectingInput::default()
+        };
+        let paths = Paths::from_config(&cfg);
+        assert_eq!(paths.kernel_args, "/run/cmdline");
+        assert_eq!(paths.aleph_version, OS_ALEPH_VERSION_FILE);
+        assert_eq!(paths.uptime, UPTIME_FILE);
+
+        let paths = paths.with_root(Path::new("/mnt/sysroot"));
+        assert_eq!(paths.kernel_args, "/mnt/sysroot/run/cmdline");
+        assert_eq!(paths.aleph_version, "/mnt/sysroot/.coreos-aleph-version.json");
+        assert_eq!(paths.dmi_id_dir, "/mnt/sysroot/sys/class/dmi/id");
+        assert_eq!(paths.machine_id, "/mnt/sysroot/etc/machine-id");
+    }
+
+    #[test]
+    fn test_with_paths() {
+        let root = fixture_root("cmdline-aws", "aleph-version-new.json", "afterburn-aws");
+        let cfg = inputs::CollectingInput {
+            level: "full".to_string(),
+            ..inputs::CollectingInput::default()
+        };
+        let id = Identity::with_paths(&cfg, &Paths::default().with_root(root.path())).unwrap();
+
+        assert_eq!(id.platform, "aws");
+        assert_eq!(id.original_os_version, Some("36.20220618.3.1".to_string()));
+        assert_eq!(id.instance_type, Some("m5.large".to_string()));
+        assert_eq!(id.uptime_seconds, Some(12346));
+        assert_eq!(id.memory_mb, Some(3931));
+        assert_eq!(id.total_memory_kb, Some(4_026_348));
+        assert_eq!(id.cpu_count, Some(4));
+        assert_eq!(id.kernel_version, Some("5.3.7-301.fc31.x86_64".to_string()));
+        assert_eq!(id.fips_enabled, Some(false));
+        assert_eq!(id.root_fs_type, Some("xfs".to_string()));
+        assert_eq!(id.selinux_mode, Some("disabled".to_string()));
+        assert_eq!(id.firmware, Some("bios".to_string()));
+        assert_eq!(id.secure_boot, Some(false));
+        assert_eq!(id.ignition_spec_version, Some("3.3.0".to_string()));
+        assert_eq!(
+            id.machine_id_hash,
+            Some("ed2a68d508fddc8fa406a9a242392c18101e1058467b6e87651854bb4f92e4c2".to_string())
+        );
+
+        let empty = tempfile::tempdir().unwrap();
+        match Identity::with_paths(&cfg, &Paths::default().with_root(empty.path())).unwrap_err() {
+            PingerError::PlatformDetection(e) => assert!(
+                e.to_string().starts_with("platform detection failed: "),
+                "{}",
+                e
+            ),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_collecting_level() {
+        let tests = vec![
+            ("none", CollectingLevel::None),
+            ("off", CollectingLevel::None),
+            ("minimal", CollectingLevel::Minimal),
+            ("full", CollectingLevel::Full),
+            ("verbose", CollectingLevel::Minimal),
+            ("", CollectingLevel::Minimal),
+        ];
+        for (level, expected) in tests {
+            let cfg = inputs::CollectingInput {
+                level: level.to_string(),
+                ..inputs::CollectingInput::default()
+            };
+            assert_eq!(configured_level(&cfg).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_strict_level() {
+        let cfg = inputs::CollectingInput {
+            level: "minmal".to_string(),
+            strict_level: true,
+            ..inputs::CollectingInput::default()
+        };
+        match Identity::new(&cfg).unwrap_err() {
+            PingerError::InvalidLevel(e) => {
+                assert_eq!(e.level, "minmal");
+                assert_eq!(
+                    e.to_string(),
+                    "invalid collecting level 'minmal', expected one of: none, minimal, full"
+                );
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+        match Identity::from_fixtures(&cfg, Path::new("tests/fixtures/identity")) {
+            Err(PingerError::InvalidLevel(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        let cfg = inputs::CollectingInput {
+            strict_level: false,
+            ..cfg
+        };
+        assert_eq!(configured_level(&cfg).unwrap(), CollectingLevel::Minimal);
+        let id = Identity::from_fixtures(&cfg, Path::new("tests/fixtures/identity")).unwrap();
+        assert_eq!(id.level, CollectingLevel::Minimal);
+    }
+
+    #[test]
+    fn test_kernel_args() {
+        fn keys(args: &Option<BTreeMap<String, String>>) -> Option<Vec<&str>> {
+            args.as_ref()
+                .map(|args| args.keys().map(String::as_str).collect())
+        }
+
+        let allowlist = vec!["mitigations".to_string(), "nosuchflag".to_string()];
+        let tests = vec![
+            (CollectingLevel::Minimal, None),
+            (CollectingLevel::Full, Some(vec!["mitigations"])),
+        ];
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        for (level, expected) in tests {
+            let id = Identity::try_from_paths(
+                level,
+                &Paths::default().with_root(root.path()),
+                &rpm_ostree::Status::with_runner(|| failure::bail!("rpm-ostree not found")),
+                &allowlist,
+            )
+            .unwrap();
+
+            assert_eq!(keys(&id.kernel_args), expected, "failed level: {}", level);
+            assert_eq!(keys(&id.kernel_flags), expected, "failed level: {}", level);
+        }
+    }
+
+    #[test]
+    fn test_pinned_checksum() {
+        let status = rpm_ostree::Status::with_runner(|| {
+            Ok(std::fs::read(
+                "tests/fixtures/rpm-ostree-status-pinned.json",
+            )?)
+        });
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        let id = Identity::try_from_paths(
+            CollectingLevel::Minimal,
+            &Paths::default().with_root(root.path()),
+            &status,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(id.current_os_version, None);
+        assert_eq!(id.stream, None);
+        let vars = id.get_data();
+        assert!(!vars.contains_key("current_os_version"));
+        assert_eq!(
+            vars.get("current_os_checksum"),
+            Some(&"4b8d4e5a0f7e8a1a5f2c3e1f0b6b4a8e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pending_os_version() {
+        let status = rpm_ostree::Status::with_runner(|| {
+            Ok(std::fs::read(
+                "tests/fixtures/rpm-ostree-status-staged.json",
+            )?)
+        });
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        let id = Identity::try_from_paths(
+            CollectingLevel::Minimal,
+            &Paths::default().with_root(root.path()),
+            &status,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            id.current_os_version,
+            Some("30.20190923.dev.2-2".to_string())
+        );
+        assert_eq!(id.pending_os_version, Some("30.20191002.0".to_string()));
+        assert_eq!(
+            id.get_data().get("pending_os_version"),
+            Some(&"30.20191002.0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pending_update() {
+        let tests = vec![
+            (CollectingLevel::Minimal, "tests/fixtures/rpm-ostree-status-staged.json", None),
+            (CollectingLevel::Full, "tests/fixtures/rpm-ostree-status-staged.json", Some(true)),
+            (CollectingLevel::Full, "tests/fixtures/rpm-ostree-status.json", Some(false)),
+        ];
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        for (level, fixture, expected) in tests {
+            let status = rpm_ostree::Status::with_runner(move || Ok(std::fs::read(fixture)?));
+            let id = Identity::try_from_paths(
+                level,
+                &Paths::default().with_root(root.path()),
+                &status,
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(id.pending_update, expected, "failed testcase: {}", fixture);
+        }
+
+        let id = Identity::try_from_paths(
+            CollectingLevel::Full,
+            &Paths::default().with_root(root.path()),
+            &rpm_ostree::Status::with_runner(|| failure::bail!("rpm-ostree not found")),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(id.pending_update, None);
+    }
+
+    #[test]
+    fn test_layered_packages() {
+        let tests = vec![
+            (
+                CollectingLevel::Minimal,
+                "tests/fixtures/rpm-ostree-status-layered.json",
+                None,
+                None,
+            ),
+            (
+                CollectingLevel::Full,
+                "tests/fixtures/rpm-ostree-status-layered.json",
+                Some(vec!["htop".to_string(), "vim-enhanced".to_string()]),
+                Some(true),
+            ),
+            (
+                CollectingLevel::Full,
+                "tests/fixtures/rpm-ostree-status.json",
+                Some(vec![]),
+                Some(false),
+            ),
+        ];
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        for (level, fixture, expected, expected_layered) in tests {
+            let status = rpm_ostree::Status::with_runner(move || Ok(std::fs::read(fixture)?));
+            let id = Identity::try_from_paths(
+                level,
+                &Paths::default().with_root(root.path()),
+                &status,
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(id.layered_packages, expected, "failed level: {}", level);
+            assert_eq!(
+                id.has_layered_packages, expected_layered,
+                "failed level: {}",
+                level
+            );
+            assert_eq!(
+                id.to_json_value().unwrap()["has_layered_packages"],
+                serde_json::json!(expected_layered)
+            );
+            // An empty list is omitted, like one which was not collected.
+            assert_eq!(
+                id.get_data().contains_key("layered_packages"),
+                expected
+                    .as_ref()
+                    .is_some_and(|packages| !packages.is_empty()),
+                "failed level: {}",
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn test_vmware_no_metadata() {
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        let paths = Paths::default().with_root(root.path());
+        std::fs::write(&paths.kernel_args, "ignition.platform.id=vmware\n").unwrap();
+        std::fs::remove_file(&paths.afterburn_metadata).unwrap();
+        let id = Identity::try_from_paths(
+            CollectingLevel::Full,
+            &paths,
+            &rpm_ostree::Status::with_runner(|| failure::bail!("rpm-ostree not found")),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(id.platform, Platform::VMware);
+        assert_eq!(id.instance_type, None);
+        assert_eq!(id.region, None);
+        assert!(!id.get_data().contains_key("instance_type"));
+    }
+
+    #[test]
+    fn test_collected_at() {
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        let id = Identity::try_from_paths_with(
+            CollectingLevel::Minimal,
+            &Paths::default().with_root(root.path()),
+            &rpm_ostree::Status::with_runner(|| failure::bail!("rpm-ostree not found")),
+            &[],
+            || failure::bail!("systemd-detect-virt not found"),
+            || UNIX_EPOCH + Duration::from_secs(1_570_017_600),
+        )
+        .unwrap();
+
+        assert_eq!(id.collected_at, Some("2019-10-02T12:00:00Z".to_string()));
+        let value = id.to_json_value().unwrap();
+        assert_eq!(value["collected_at"], "2019-10-02T12:00:00Z");
+        assert!(!id.get_data().contains_key("collected_at"));
+    }
+
+    #[test]
+    fn test_agent_version() {
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        let id = Identity::try_from_paths(
+            CollectingLevel::Minimal,
+            &Paths::default().with_root(root.path()),
+            &rpm_ostree::Status::with_runner(|| failure::bail!("rpm-ostree not found")),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(id.agent_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            id.get_data().get("agent_version"),
+            Some(&env!("CARGO_PKG_VERSION").to_string())
+        );
+    }
+
+    #[test]
+    fn test_missing_aleph_version() {
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-aws");
+        let paths = Paths::default().with_root(root.path());
+        std::fs::remove_file(&paths.aleph_version).unwrap();
+        let id = Identity::try_from_paths(
+            CollectingLevel::Full,
+            &paths,
+            &rpm_ostree::Status::with_runner(|| failure::bail!("rpm-ostree not found")),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(id.original_os_version, None);
+        assert_eq!(id.original_major_version, None);
+        assert_eq!(id.original_image_id, None);
+        assert_eq!(id.platform, Platform::Aws);
+        assert_eq!(id.instance_type, Some("m5.large".to_string()));
+        assert!(!id.get_data().contains_key("original_os_version"));
+    }
+
+    #[test]
+    fn test_missing_metadata_keys() {
+        let root = fixture_root("cmdline-aws", "aleph-version.json", "afterburn-hetzner");
+        let id = Identity::try_from_paths(
+            CollectingLevel::Full,
+            &Paths::default().with_root(root.path()),
+            &rpm_ostree::Status::with_runner(|| failure::bail!("rpm-ostree not found")),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(id.platform, "aws");
+        assert_eq!(id.instance_type, None);
+        assert_eq!(id.region, None);
+        assert_eq!(id.availability_zone, None);
+    }
+
+    #[test]
+    fn test_none() {
+        let id = Identity::mock_default("none");
+        let vars = id.get_data();
+
+        assert!(!vars.contains_key("platform"));
+        assert!(!vars.contains_key("original_os_version"));
+        assert!(!vars.contains_key("current_os_version"));
+        assert!(vars.is_empty());
+    }
+
+    #[test]
+    fn test_none_no_reads() {
+        // Files replaced by directories cannot be read, and failing to read
+        // the cmdline or the aleph version file fails the collection.
+        let root = tempfile::tempdir().unwrap();
+        let paths = Paths::default().with_root(root.path());
+        for path in &[
+            &paths.kernel_args,
+            &paths.aleph_version,
+            &paths.afterburn_metadata,
+            &paths.machine_id,
+        ] {
+            std::fs::create_dir_all(path).unwrap();
+        }
+        let failing = rpm_ostree::Status::with_runner(|| failure::bail!("rpm-ostree not found"));
+        Identity::try_from_paths(CollectingLevel::Minimal, &paths, &failing, &[]).unwrap_err();
+
+        let id = Identity::try_from_paths_with(
+            CollectingLevel::None,
+            &paths,
+            &rpm_ostree::Status::with_runner(|| panic!("rpm-ostree must not run")),
+            &[],
+            || panic!("virtualization must not be detected"),
+            || panic!("the collection must not be timestamped"),
+        )
+        .unwrap();
+
+        assert_eq!(id.level, CollectingLevel::None);
+        assert!(id.get_data().is_empty());
+    }
+}
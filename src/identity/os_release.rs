@@ -0,0 +1,281 @@
+//! OS version parsing - utility functions
+
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use serde::Deserialize;
+use std::path::Path;
+use thiserror::Error;
+
+/// Aleph version file, written at image build time.
+///
+/// Older images only record `version`, newer ones record `build`, `ref`
+/// and `imgid` instead.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub(crate) struct AlephVersion {
+    #[serde(default)]
+    pub(crate) version: Option<String>,
+    #[serde(default)]
+    pub(crate) build: Option<String>,
+    #[serde(default, rename = "ref")]
+    pub(crate) ostree_ref: Option<String>,
+    #[serde(default)]
+    pub(crate) imgid: Option<String>,
+}
+
+impl AlephVersion {
+    /// Return the OS version, from either schema.
+    pub(crate) fn os_version(&self) -> Option<&str> {
+        self.version.as_deref().or(self.build.as_deref())
+    }
+}
+
+/// Error returned when the aleph version file cannot be read.
+#[derive(Debug, Error)]
+pub enum AlephError {
+    /// The file could not be opened.
+    #[error("failed to open aleph version file '{path}': {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The file is not a valid aleph version file.
+    #[error("failed to parse aleph version file '{path}': {source}")]
+    MetadataParse {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Components of a Fedora CoreOS version string, e.g. `30.20190923.dev.2-2`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct OsVersion {
+    /// Fedora major version (`30`).
+    pub(crate) major: u32,
+    /// Build date, as `YYYYMMDD` (`20190923`).
+    pub(crate) date: String,
+    /// Non-numeric stream suffix (`dev`), absent on production streams.
+    pub(crate) stream_suffix: Option<String>,
+    /// Remaining build identifier (`2-2`).
+    pub(crate) build: String,
+}
+
+/// Read the original OS version, and the ID of the image the machine was
+/// installed from (recorded by newer images only), from the aleph version file.
+///
+/// Returns `None` for both if the file does not exist, e.g. on machines
+/// provisioned before it was introduced. The version is `None`, with a
+/// warning, if it is missing or does not have the `NN.YYYYMMDD.<build>` shape
+/// parsed by `parse_os_version`.
+pub(crate) fn read_original(fpath: &str) -> Result<(Option<String>, Option<String>), AlephError> {
+    let aleph = match read_optional_aleph(fpath)? {
+        Some(aleph) => aleph,
+        None => return Ok((None, None)),
+    };
+    let version = match aleph.os_version() {
+        Some(version) => match parse_os_version(version) {
+            Ok(_) => Some(version.to_string()),
+            Err(e) => {
+                warn!(
+                    "invalid OS version in aleph version file '{}': {}",
+                    fpath, e
+                );
+                None
+            }
+        },
+        None => {
+            warn!("no OS version in aleph version file '{}'", fpath);
+            None
+        }
+    };
+
+    Ok((version, aleph.imgid))
+}
+
+/// Read the aleph version file, if it exists.
+fn read_optional_aleph(fpath: &str) -> Result<Option<AlephVersion>, AlephError> {
+    if !Path::new(fpath).exists() {
+        debug!("aleph version file '{}' not found", fpath);
+        return Ok(None);
+    }
+    read_aleph(fpath).map(Some)
+}
+
+/// Read the aleph version file.
+pub(crate) fn read_aleph(fpath: &str) -> Result<AlephVersion, AlephError> {
+    let file = std::fs::File::open(fpath).map_err(|source| AlephError::Io {
+        path: fpath.to_string(),
+        source,
+    })?;
+    let aleph = serde_json::from_reader(std::io::BufReader::new(file)).map_err(|source| {
+        AlephError::MetadataParse {
+            path: fpath.to_string(),
+            source,
+        }
+    })?;
+
+    Ok(aleph)
+}
+
+/// Split an OS version string into its components.
+pub(crate) fn parse_os_version(version: &str) -> Fallible<OsVersion> {
+    let invalid = || format_err!("invalid OS version '{}'", version);
+    let mut parts = version.splitn(3, '.');
+
+    let major = parts
+        .next()
+        .and_then(|p| p.parse().ok())
+        .ok_or_else(invalid)?;
+    let date = parts
+        .next()
+        .filter(|p| p.len() == 8 && p.chars().all(|c| c.is_ascii_digit()))
+        .ok_or_else(invalid)?
+        .to_string();
+    let rest = parts.next().filter(|p| !p.is_empty()).ok_or_else(invalid)?;
+
+    let (stream_suffix, build) = match rest.splitn(2, '.').collect::<Vec<_>>().as_slice() {
+        [suffix, build] if !suffix.chars().all(|c| c.is_ascii_digit()) => {
+            (Some(suffix.to_string()), build.to_string())
+        }
+        _ => (None, rest.to_string()),
+    };
+    if build.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(OsVersion {
+        major,
+        date,
+        stream_suffix,
+        build,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_original_os_version() {
+        let (version, _) = read_original("tests/fixtures/aleph-version.json").unwrap();
+        assert_eq!(version, Some("30.20190905.0".to_string()));
+        let (version, _) = read_original("tests/fixtures/aleph-version-new.json").unwrap();
+        assert_eq!(version, Some("36.20220618.3.1".to_string()));
+        assert_eq!(read_original("/nonexistent").unwrap(), (None, None));
+        for fpath in &[
+            "tests/fixtures/machine-id",
+            "tests/fixtures/aleph-version-malformed.json",
+        ] {
+            match read_original(fpath).unwrap_err() {
+                AlephError::MetadataParse { path, .. } => assert_eq!(&path, fpath),
+                e => panic!("unexpected error: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_aleph_unreadable() {
+        // A socket exists, but cannot be opened as a file.
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("aleph.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&fpath).unwrap();
+        let fpath = fpath.to_str().unwrap();
+
+        match read_original(fpath).unwrap_err() {
+            AlephError::Io { path, source } => {
+                assert_eq!(path, fpath);
+                assert_eq!(source.raw_os_error(), Some(libc::ENXIO));
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_read_original_os_version_validated() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("aleph.json");
+        let fpath = fpath.to_str().unwrap();
+
+        std::fs::write(fpath, r#"{"version": "30.20190923.dev.2-2"}"#).unwrap();
+        assert_eq!(
+            read_original(fpath).unwrap().0,
+            Some("30.20190923.dev.2-2".to_string())
+        );
+
+        for version in &["", "garbage", "30.2019-09-23.0"] {
+            std::fs::write(fpath, format!(r#"{{"version": "{}"}}"#, version)).unwrap();
+            assert_eq!(read_original(fpath).unwrap().0, None, "{}", version);
+        }
+
+        std::fs::write(fpath, r#"{"imgid": "fedora-coreos.raw"}"#).unwrap();
+        assert_eq!(
+            read_original(fpath).unwrap(),
+            (None, Some("fedora-coreos.raw".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_read_original_image_id() {
+        let (_, imgid) = read_original("tests/fixtures/aleph-version.json").unwrap();
+        assert_eq!(imgid, None);
+        let (_, imgid) = read_original("tests/fixtures/aleph-version-new.json").unwrap();
+        assert_eq!(
+            imgid,
+            Some("fedora-coreos-36.20220618.3.1-metal.x86_64.raw".to_string())
+        );
+    }
+
+    #[test]
+    fn test_read_aleph() {
+        let aleph = read_aleph("tests/fixtures/aleph-version.json").unwrap();
+        let expected = AlephVersion {
+            version: Some("30.20190905.0".to_string()),
+            ..AlephVersion::default()
+        };
+        assert_eq!(aleph, expected);
+
+        let aleph = read_aleph("tests/fixtures/aleph-version-new.json").unwrap();
+        let expected = AlephVersion {
+            version: None,
+            build: Some("36.20220618.3.1".to_string()),
+            ostree_ref: Some("fedora/x86_64/coreos/stable".to_string()),
+            imgid: Some("fedora-coreos-36.20220618.3.1-metal.x86_64.raw".to_string()),
+        };
+        assert_eq!(aleph, expected);
+    }
+
+    #[test]
+    fn test_parse_os_version() {
+        let tests = vec![
+            ("30.20190923.dev.2-2", 30, "20190923", Some("dev"), "2-2"),
+            ("30.20190905.0", 30, "20190905", None, "0"),
+            ("39.20231101.3.0", 39, "20231101", None, "3.0"),
+        ];
+        for (version, major, date, stream_suffix, build) in tests {
+            let expected = OsVersion {
+                major,
+                date: date.to_string(),
+                stream_suffix: stream_suffix.map(String::from),
+                build: build.to_string(),
+            };
+            assert_eq!(parse_os_version(version).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_os_version_invalid() {
+        let tests = vec![
+            "",
+            "30",
+            "30.20190923",
+            "30.20190923.",
+            "30.20190923.dev.",
+            "thirty.20190923.0",
+            "30.2019092.0",
+        ];
+        for version in tests {
+            parse_os_version(version).unwrap_err();
+        }
+    }
+}
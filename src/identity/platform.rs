@@ -0,0 +1,473 @@
+//! Platform detection - utility functions
+//!
+//! The platform ID is read from the kernel cmdline, falling back to DMI
+//! (sysfs) vendor and product information when the flag is absent.
+//!
+//! NOTE: this is not a complete/correct cmdline parser, as it implements
+//! just enough logic to extract the platform ID value.
+
+use log::{debug, trace};
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Platform key.
+const CMDLINE_PLATFORM_FLAG: &str = "ignition.platform.id";
+
+/// Platform the machine runs on, as identified by its Ignition platform ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Platform {
+    Aliyun,
+    Aws,
+    Azure,
+    AzureStack,
+    DigitalOcean,
+    Exoscale,
+    Gcp,
+    Hetzner,
+    IbmCloud,
+    KubeVirt,
+    Metal,
+    Nutanix,
+    OpenStack,
+    OracleCloud,
+    PowerVs,
+    Qemu,
+    VMware,
+    Vultr,
+    /// Any other platform ID, kept as is.
+    Unknown(String),
+}
+
+impl Platform {
+    /// Map a platform ID to a platform, unknown IDs included.
+    pub(crate) fn from_id(id: &str) -> Self {
+        match id {
+            "aliyun" => Platform::Aliyun,
+            "aws" => Platform::Aws,
+            "azure" => Platform::Azure,
+            "azurestack" => Platform::AzureStack,
+            "digitalocean" => Platform::DigitalOcean,
+            "exoscale" => Platform::Exoscale,
+            "gcp" => Platform::Gcp,
+            "hetzner" => Platform::Hetzner,
+            "ibmcloud" => Platform::IbmCloud,
+            "kubevirt" => Platform::KubeVirt,
+            "metal" => Platform::Metal,
+            "nutanix" => Platform::Nutanix,
+            "openstack" => Platform::OpenStack,
+            "oraclecloud" => Platform::OracleCloud,
+            "powervs" => Platform::PowerVs,
+            "qemu" => Platform::Qemu,
+            "vmware" => Platform::VMware,
+            "vultr" => Platform::Vultr,
+            _ => Platform::Unknown(id.to_string()),
+        }
+    }
+
+    /// Return the canonical platform ID.
+    pub(crate) fn as_str(&self) -> &str {
+        match self {
+            Platform::Aliyun => "aliyun",
+            Platform::Aws => "aws",
+            Platform::Azure => "azure",
+            Platform::AzureStack => "azurestack",
+            Platform::DigitalOcean => "digitalocean",
+            Platform::Exoscale => "exoscale",
+            Platform::Gcp => "gcp",
+            Platform::Hetzner => "hetzner",
+            Platform::IbmCloud => "ibmcloud",
+            Platform::KubeVirt => "kubevirt",
+            Platform::Metal => "metal",
+            Platform::Nutanix => "nutanix",
+            Platform::OpenStack => "openstack",
+            Platform::OracleCloud => "oraclecloud",
+            Platform::PowerVs => "powervs",
+            Platform::Qemu => "qemu",
+            Platform::VMware => "vmware",
+            Platform::Vultr => "vultr",
+            Platform::Unknown(id) => id,
+        }
+    }
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        Platform::Unknown(String::new())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = Infallible;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from_id(id))
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for Platform {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl PartialEq<&str> for Platform {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Error returned when the platform could not be detected from any source.
+#[derive(Debug)]
+pub struct PlatformDetectionError {
+    cmdline_path: String,
+    dmi_dir: Option<String>,
+}
+
+impl fmt::Display for PlatformDetectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "platform detection failed: no '{}' flag in cmdline file ({})",
+            CMDLINE_PLATFORM_FLAG, self.cmdline_path
+        )?;
+        match &self.dmi_dir {
+            Some(dir) => write!(f, " and no known vendor in DMI directory ({})", dir),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for PlatformDetectionError {}
+
+/// Read platform value from cmdline file, falling back to the DMI
+/// directory (if any) when the cmdline does not contain a platform ID.
+///
+/// If the cmdline contains several platform IDs, the last one is used.
+pub(crate) fn get_platform(
+    cmdline_path: &str,
+    dmi_dir: Option<&str>,
+) -> Result<Platform, PlatformDetectionError> {
+    match std::fs::read_to_string(cmdline_path) {
+        Ok(contents) => {
+            if let Some(id) = find_flag_value(CMDLINE_PLATFORM_FLAG, &contents) {
+                trace!("found platform id: {}", id);
+                return Ok(Platform::from_id(&id));
+            }
+        }
+        Err(e) => debug!("failed to read cmdline file '{}': {}", cmdline_path, e),
+    }
+
+    if let Some(dir) = dmi_dir {
+        if let Some(platform) = read_dmi_platform(dir) {
+            trace!("detected platform from DMI: {}", platform);
+            return Ok(platform);
+        }
+    }
+
+    Err(PlatformDetectionError {
+        cmdline_path: cmdline_path.to_string(),
+        dmi_dir: dmi_dir.map(String::from),
+    })
+}
+
+/// Infer the platform from DMI vendor and product name.
+fn read_dmi_platform(dmi_dir: &str) -> Option<Platform> {
+    let read_attr = |name: &str| match std::fs::read_to_string(Path::new(dmi_dir).join(name)) {
+        Ok(value) => Some(value.trim().to_string()),
+        Err(e) => {
+            debug!("failed to read DMI attribute '{}': {}", name, e);
+            None
+        }
+    };
+
+    let sys_vendor = read_attr("sys_vendor")?;
+    let product_name = read_attr("product_name").unwrap_or_default();
+    platform_from_dmi(&sys_vendor, &product_name)
+}
+
+/// Map DMI vendor and product name to a platform.
+fn platform_from_dmi(sys_vendor: &str, product_name: &str) -> Option<Platform> {
+    let platform = match sys_vendor {
+        "Alibaba Cloud" => Platform::Aliyun,
+        "Amazon EC2" => Platform::Aws,
+        "DigitalOcean" => Platform::DigitalOcean,
+        "Google" => Platform::Gcp,
+        "Hetzner" => Platform::Hetzner,
+        "Microsoft Corporation" if product_name == "Virtual Machine" => Platform::Azure,
+        "OpenStack Foundation" => Platform::OpenStack,
+        "VMware, Inc." => Platform::VMware,
+        "Vultr" => Platform::Vultr,
+        _ if product_name == "OpenStack Nova" => Platform::OpenStack,
+        _ => return None,
+    };
+    Some(platform)
+}
+
+/// Tokenize the kernel cmdline into flags and their value.
+///
+/// Bare flags (e.g. `rw`) map to `None`. If a flag is repeated, the last
+/// occurrence wins.
+pub(crate) fn parse_kernel_flags(cmdline: &str) -> HashMap<String, Option<String>> {
+    cmdline
+        .split_whitespace()
+        .map(|arg| {
+            let mut parts = arg.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().to_string();
+            let value = parts.next().map(String::from);
+            (key, value)
+        })
+        .collect()
+}
+
+/// Find the value of the given flag in the kernel cmdline.
+///
+/// If the flag is repeated, the last occurrence wins, as for the kernel
+/// itself; an empty last value gives `None`.
+fn find_flag_value(flagname: &str, cmdline: &str) -> Option<String> {
+    // split the contents into elements and keep key-value tuples only.
+    let params: Vec<(&str, &str)> = cmdline
+        .split(' ')
+        .filter_map(|s| {
+            let kv: Vec<&str> = s.splitn(2, '=').collect();
+            match kv.len() {
+                2 => Some((kv[0], kv[1])),
+                _ => None,
+            }
+        })
+        .collect();
+
+    let (_, val) = params.into_iter().rev().find(|(key, _)| *key == flagname)?;
+    let bare_val = strip_quotes(val.trim());
+    if bare_val.is_empty() {
+        return None;
+    }
+    Some(bare_val.to_string())
+}
+
+/// Strip matching surrounding single or double quotes from a flag value.
+///
+/// Unbalanced quotes are left as is.
+fn strip_quotes(val: &str) -> &str {
+    for quote in &['"', '\''] {
+        if val.len() >= 2 && val.starts_with(*quote) && val.ends_with(*quote) {
+            return &val[1..val.len() - 1];
+        }
+    }
+    val
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_flag() {
+        let flagname = "ignition.platform.id";
+        let tests = vec![
+            ("", None),
+            ("foo=bar", None),
+            ("ignition.platform.id", None),
+            ("ignition.platform.id=", None),
+            ("ignition.platform.id=\t", None),
+            ("ignition.platform.id=aws", Some("aws".to_string())),
+            ("ignition.platform.id=\taws", Some("aws".to_string())),
+            ("ignition.platform.id=aws\n", Some("aws".to_string())),
+            ("foo=bar ignition.platform.id=aws", Some("aws".to_string())),
+            ("ignition.platform.id=aws foo=bar", Some("aws".to_string())),
+            ("ignition.platform.id=\"aws\"", Some("aws".to_string())),
+            ("ignition.platform.id='aws'\n", Some("aws".to_string())),
+            ("ignition.platform.id=\"\"", None),
+            ("ignition.platform.id=''", None),
+            ("ignition.platform.id=\"aws", Some("\"aws".to_string())),
+            ("ignition.platform.id=aws'", Some("aws'".to_string())),
+            ("ignition.platform.id=\"aws'", Some("\"aws'".to_string())),
+            ("ignition.platform.id=\"", Some("\"".to_string())),
+            (
+                "ignition.platform.id=gcp ignition.platform.id=aws",
+                Some("aws".to_string()),
+            ),
+            ("ignition.platform.id=aws ignition.platform.id=", None),
+        ];
+        for (tcase, tres) in tests {
+            let res = find_flag_value(flagname, tcase);
+            assert_eq!(res, tres, "failed testcase: '{}'", tcase);
+        }
+    }
+
+    #[test]
+    fn test_parse_kernel_flags() {
+        let flags = parse_kernel_flags("rw fips=1 enforcing=0 console=tty0 console=ttyS0 a=b=c\n");
+        let expected: HashMap<String, Option<String>> = vec![
+            ("rw", None),
+            ("fips", Some("1")),
+            ("enforcing", Some("0")),
+            ("console", Some("ttyS0")),
+            ("a", Some("b=c")),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.map(String::from)))
+        .collect();
+        assert_eq!(flags, expected);
+
+        let flags = parse_kernel_flags("systemd.unified_cgroup_hierarchy fips=");
+        assert_eq!(flags["systemd.unified_cgroup_hierarchy"], None);
+        assert_eq!(flags["fips"], Some(String::new()));
+
+        assert!(parse_kernel_flags("").is_empty());
+    }
+
+    #[test]
+    fn test_vmware_platform() {
+        let cmdline = "BOOT_IMAGE=(hd0,gpt1)/ostree/fedora-coreos/vmlinuz mitigations=auto,nosmt \
+                       console=tty0 ignition.platform.id=vmware root=UUID=deadbeef";
+        let platform = find_flag_value(CMDLINE_PLATFORM_FLAG, cmdline);
+        assert_eq!(platform, Some("vmware".to_string()));
+    }
+
+    #[test]
+    fn test_ibm_platforms() {
+        let tests = vec![
+            (
+                "BOOT_IMAGE=/vmlinuz console=ttyS0 ignition.platform.id=ibmcloud",
+                "ibmcloud",
+            ),
+            (
+                "BOOT_IMAGE=/vmlinuz console=hvc0 ignition.platform.id=powervs",
+                "powervs",
+            ),
+        ];
+        for (cmdline, expected) in tests {
+            let platform = find_flag_value(CMDLINE_PLATFORM_FLAG, cmdline);
+            assert_eq!(platform, Some(expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_small_cloud_platforms() {
+        for expected in &["digitalocean", "hetzner", "vultr"] {
+            let cmdline = format!(
+                "BOOT_IMAGE=/vmlinuz console=ttyS0 ignition.platform.id={}",
+                expected
+            );
+            let platform = find_flag_value(CMDLINE_PLATFORM_FLAG, &cmdline);
+            assert_eq!(platform, Some(expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_platform_from_dmi() {
+        let tests = vec![
+            ("Amazon EC2", "m5.large", Some("aws")),
+            ("Google", "Google Compute Engine", Some("gcp")),
+            ("Microsoft Corporation", "Virtual Machine", Some("azure")),
+            ("Microsoft Corporation", "Surface Laptop", None),
+            ("VMware, Inc.", "VMware7,1", Some("vmware")),
+            ("Red Hat", "OpenStack Nova", Some("openstack")),
+            ("Dell Inc.", "PowerEdge R640", None),
+        ];
+        for (vendor, product, expected) in tests {
+            let res = platform_from_dmi(vendor, product).map(|p| p.to_string());
+            let expected = expected.map(String::from);
+            assert_eq!(res, expected, "failed testcase: '{}' '{}'", vendor, product);
+        }
+    }
+
+    #[test]
+    fn test_parse_platform() {
+        let tests = vec![
+            ("aliyun", Platform::Aliyun),
+            ("aws", Platform::Aws),
+            ("azure", Platform::Azure),
+            ("azurestack", Platform::AzureStack),
+            ("digitalocean", Platform::DigitalOcean),
+            ("exoscale", Platform::Exoscale),
+            ("gcp", Platform::Gcp),
+            ("hetzner", Platform::Hetzner),
+            ("ibmcloud", Platform::IbmCloud),
+            ("kubevirt", Platform::KubeVirt),
+            ("metal", Platform::Metal),
+            ("nutanix", Platform::Nutanix),
+            ("openstack", Platform::OpenStack),
+            ("oraclecloud", Platform::OracleCloud),
+            ("powervs", Platform::PowerVs),
+            ("qemu", Platform::Qemu),
+            ("vmware", Platform::VMware),
+            ("vultr", Platform::Vultr),
+            ("newcloud", Platform::Unknown("newcloud".to_string())),
+            ("AWS", Platform::Unknown("AWS".to_string())),
+        ];
+        for (id, expected) in tests {
+            let platform: Platform = id.parse().unwrap();
+            assert_eq!(platform, expected, "failed testcase: '{}'", id);
+            assert_eq!(platform.to_string(), id);
+            assert_eq!(serde_json::to_value(&platform).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn test_get_platform_cmdline() {
+        let platform = get_platform("tests/fixtures/cmdline-aws", Some("/nonexistent")).unwrap();
+        assert_eq!(platform, Platform::Aws);
+    }
+
+    #[test]
+    fn test_get_platform_dmi_fallback() {
+        let platform = get_platform(
+            "tests/fixtures/cmdline-no-platform",
+            Some("tests/fixtures/dmi-vmware"),
+        )
+        .unwrap();
+        assert_eq!(platform, Platform::VMware);
+    }
+
+    #[test]
+    fn test_get_platform_quoted() {
+        let dir = tempfile::tempdir().unwrap();
+        let cmdline = dir.path().join("cmdline");
+        let cmdline = cmdline.to_str().unwrap();
+
+        std::fs::write(cmdline, "console=ttyS0 ignition.platform.id=\"aws\"\n").unwrap();
+        assert_eq!(get_platform(cmdline, None).unwrap(), Platform::Aws);
+
+        std::fs::write(cmdline, "console=ttyS0 ignition.platform.id=\"\"\n").unwrap();
+        let err = get_platform(cmdline, None).unwrap_err();
+        assert_eq!(err.cmdline_path, cmdline);
+    }
+
+    #[test]
+    fn test_get_platform_repeated() {
+        let dir = tempfile::tempdir().unwrap();
+        let cmdline = dir.path().join("cmdline");
+        let cmdline = cmdline.to_str().unwrap();
+        std::fs::write(
+            cmdline,
+            "ignition.platform.id=metal console=ttyS0 ignition.platform.id=qemu\n",
+        )
+        .unwrap();
+        assert_eq!(get_platform(cmdline, None).unwrap(), Platform::Qemu);
+    }
+
+    #[test]
+    fn test_get_platform_detection_failed() {
+        let tests = vec![
+            ("/nonexistent", None),
+            ("tests/fixtures/cmdline-no-platform", None),
+            ("tests/fixtures/cmdline-no-platform", Some("/nonexistent")),
+        ];
+        for (cmdline_path, dmi_dir) in tests {
+            let err = get_platform(cmdline_path, dmi_dir).unwrap_err();
+            assert_eq!(err.cmdline_path, cmdline_path);
+            assert_eq!(err.dmi_dir.as_deref(), dmi_dir);
+        }
+    }
+}
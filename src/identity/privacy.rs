@@ -0,0 +1,59 @@
+//! Privacy-preserving identifiers - utility functions
+
+use super::machine_id;
+use failure::{Fallible, ResultExt};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Application salt keying the machine ID hash, so that the hash cannot be
+/// correlated with identifiers derived from the machine ID by other programs.
+pub(crate) const MACHINE_ID_SALT: &[u8] = b"fedora-coreos-pinger:7b1f3a9c2e5d4f8a";
+
+/// Read the machine ID and return its hex-encoded HMAC-SHA256, keyed with `salt`.
+///
+/// The hash is stable for a given machine, allowing deduplication of
+/// reports, but the raw machine ID cannot be recovered from it.
+pub(crate) fn hashed_machine_id(fpath: &str, salt: &[u8]) -> Fallible<String> {
+    let machine_id = machine_id::read_machine_id(fpath)?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(salt).context("failed to create HMAC key")?;
+    mac.update(machine_id.as_bytes());
+    let hmac = mac.finalize().into_bytes();
+
+    Ok(machine_id::to_hex(&hmac))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashed_machine_id() {
+        let hash = hashed_machine_id("tests/fixtures/machine-id", MACHINE_ID_SALT).unwrap();
+        assert_eq!(
+            hash,
+            "ed2a68d508fddc8fa406a9a242392c18101e1058467b6e87651854bb4f92e4c2"
+        );
+        assert_eq!(
+            hash,
+            hashed_machine_id("tests/fixtures/machine-id", MACHINE_ID_SALT).unwrap()
+        );
+
+        let raw = std::fs::read_to_string("tests/fixtures/machine-id").unwrap();
+        assert_ne!(hash, raw.trim());
+        assert_ne!(
+            hash,
+            hashed_machine_id("tests/fixtures/machine-id", b"other-salt").unwrap()
+        );
+        assert_ne!(
+            hash,
+            hashed_machine_id("tests/fixtures/machine-id-other", MACHINE_ID_SALT).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hashed_machine_id_invalid() {
+        hashed_machine_id("tests/fixtures/machine-id-empty", MACHINE_ID_SALT).unwrap_err();
+        hashed_machine_id("/nonexistent", MACHINE_ID_SALT).unwrap_err();
+    }
+}
@@ -0,0 +1,77 @@
+//! SELinux state - utility functions
+
+use failure::{bail, Fallible, ResultExt};
+use std::fmt;
+use std::path::Path;
+
+/// SELinux mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SelinuxMode {
+    Enforcing,
+    Permissive,
+    Disabled,
+}
+
+impl fmt::Display for SelinuxMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self {
+            SelinuxMode::Enforcing => "enforcing",
+            SelinuxMode::Permissive => "permissive",
+            SelinuxMode::Disabled => "disabled",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+/// Read the SELinux mode from the selinuxfs mount point.
+///
+/// SELinux is disabled if selinuxfs is not mounted.
+pub(crate) fn selinux_mode(selinuxfs_dir: &str) -> Fallible<SelinuxMode> {
+    let enforce_path = Path::new(selinuxfs_dir).join("enforce");
+    if !enforce_path.exists() {
+        return Ok(SelinuxMode::Disabled);
+    }
+
+    let contents = std::fs::read_to_string(&enforce_path).context(format!(
+        "failed to read SELinux enforce file '{}'",
+        enforce_path.display()
+    ))?;
+    match contents.trim() {
+        "1" => Ok(SelinuxMode::Enforcing),
+        "0" => Ok(SelinuxMode::Permissive),
+        value => bail!(
+            "invalid value '{}' in SELinux enforce file '{}'",
+            value,
+            enforce_path.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selinux_mode() {
+        let tests = vec![
+            ("tests/fixtures/selinux-enforcing", SelinuxMode::Enforcing),
+            ("tests/fixtures/selinux-permissive", SelinuxMode::Permissive),
+            ("/nonexistent/selinux", SelinuxMode::Disabled),
+        ];
+        for (dir, expected) in tests {
+            assert_eq!(
+                selinux_mode(dir).unwrap(),
+                expected,
+                "failed testcase: {}",
+                dir
+            );
+        }
+    }
+
+    #[test]
+    fn test_selinux_mode_display() {
+        assert_eq!(SelinuxMode::Enforcing.to_string(), "enforcing");
+        assert_eq!(SelinuxMode::Permissive.to_string(), "permissive");
+        assert_eq!(SelinuxMode::Disabled.to_string(), "disabled");
+    }
+}
@@ -0,0 +1,184 @@
+//! Virtualization detection - utility functions
+
+use super::Platform;
+use failure::{Fallible, ResultExt};
+use log::debug;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output};
+
+/// Substrings of DMI product names identifying virtual machines.
+const VM_PRODUCT_NAMES: &[&str] = &[
+    "Bochs",
+    "Google Compute Engine",
+    "HVM domU",
+    "KVM",
+    "OpenStack Nova",
+    "Standard PC",
+    "Virtual Machine",
+    "VirtualBox",
+    "VMware",
+];
+
+/// Detect the virtualization technology the machine runs on, through
+/// `systemd-detect-virt`.
+///
+/// Return `None` on bare metal, where `systemd-detect-virt` exits non-zero.
+pub(crate) fn detect_virt() -> Fallible<Option<String>> {
+    detect_virt_with(|| {
+        let output = Command::new("systemd-detect-virt")
+            .output()
+            .context("failed to run systemd-detect-virt")?;
+        Ok(output)
+    })
+}
+
+/// Detect the virtualization technology from a file holding the output of
+/// `systemd-detect-virt`, instead of running it.
+pub(crate) fn detect_virt_from(fpath: &str) -> Fallible<Option<String>> {
+    detect_virt_with(|| {
+        let stdout = std::fs::read(fpath).context(format!("failed to read '{}'", fpath))?;
+        Ok(Output {
+            status: ExitStatus::from_raw(0),
+            stdout,
+            stderr: vec![],
+        })
+    })
+}
+
+/// Detect the virtualization technology from the output of the given runner.
+fn detect_virt_with<F>(runner: F) -> Fallible<Option<String>>
+where
+    F: FnOnce() -> Fallible<Output>,
+{
+    let output = runner()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let virt = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    match virt.as_str() {
+        "" | "none" => Ok(None),
+        _ => Ok(Some(virt)),
+    }
+}
+
+/// Return whether the machine runs in a VM or container, based on the
+/// result of `detect_virt`.
+///
+/// If detection failed, fall back to the DMI product name under `dmi_dir`,
+/// then to the platform: the `metal` platform is assumed to be physical
+/// hardware, other known platforms to be virtualized. Return `None` if this
+/// cannot be told.
+pub(crate) fn is_virtualized(
+    detected: &Fallible<Option<String>>,
+    dmi_dir: &str,
+    platform: &Platform,
+) -> Option<bool> {
+    if let Ok(virt) = detected {
+        return Some(virt.is_some());
+    }
+
+    let product_path = Path::new(dmi_dir).join("product_name");
+    match std::fs::read_to_string(&product_path) {
+        Ok(product) if VM_PRODUCT_NAMES.iter().any(|vm| product.contains(vm)) => return Some(true),
+        Ok(_) => {}
+        Err(e) => debug!("failed to read '{}': {}", product_path.display(), e),
+    }
+
+    match platform {
+        Platform::Metal => Some(false),
+        Platform::Unknown(_) => None,
+        _ => Some(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    /// Return a runner exiting with the given code and printing `stdout`.
+    fn mock_runner(code: i32, stdout: &'static str) -> impl FnOnce() -> Fallible<Output> {
+        move || {
+            Ok(Output {
+                status: ExitStatus::from_raw(code << 8),
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: vec![],
+            })
+        }
+    }
+
+    #[test]
+    fn test_detect_virt() {
+        let tests = vec![
+            (0, "kvm\n", Some("kvm")),
+            (0, "microsoft\n", Some("microsoft")),
+            (1, "none\n", None),
+            (0, "", None),
+        ];
+        for (code, stdout, expected) in tests {
+            assert_eq!(
+                detect_virt_with(mock_runner(code, stdout)).unwrap(),
+                expected.map(String::from),
+                "failed testcase: '{}'",
+                stdout
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_virt_failure() {
+        detect_virt_with(|| failure::bail!("systemd-detect-virt not found")).unwrap_err();
+    }
+
+    #[test]
+    fn test_detect_virt_from() {
+        let detected = detect_virt_from("tests/fixtures/identity/systemd-detect-virt");
+        assert_eq!(detected.unwrap(), Some("kvm".to_string()));
+        detect_virt_from("/nonexistent/systemd-detect-virt").unwrap_err();
+    }
+
+    #[test]
+    fn test_is_virtualized_vm() {
+        let detected = detect_virt_with(mock_runner(0, "kvm\n"));
+        assert_eq!(
+            is_virtualized(&detected, "/nonexistent", &Platform::Metal),
+            Some(true)
+        );
+
+        // DMI product name, when systemd-detect-virt is unavailable.
+        let failed = detect_virt_with(|| failure::bail!("systemd-detect-virt not found"));
+        assert_eq!(
+            is_virtualized(&failed, "tests/fixtures/dmi-vmware", &Platform::Metal),
+            Some(true)
+        );
+        assert_eq!(
+            is_virtualized(&failed, "/nonexistent", &Platform::Aws),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_is_virtualized_bare_metal() {
+        let detected = detect_virt_with(mock_runner(1, "none\n"));
+        assert_eq!(
+            is_virtualized(&detected, "tests/fixtures/dmi-vmware", &Platform::Aws),
+            Some(false)
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("product_name"), "PowerEdge R640\n").unwrap();
+        let dmi_dir = dir.path().to_str().unwrap();
+        let failed = detect_virt_with(|| failure::bail!("systemd-detect-virt not found"));
+        assert_eq!(
+            is_virtualized(&failed, dmi_dir, &Platform::Metal),
+            Some(false)
+        );
+        assert_eq!(
+            is_virtualized(&failed, dmi_dir, &Platform::from_id("newcloud")),
+            None
+        );
+    }
+}
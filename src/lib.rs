@@ -0,0 +1,35 @@
+//! Telemetry service for Fedora CoreOS.
+//!
+//! Besides the `fedora-coreos-pinger` binary, the identity collection is
+//! exposed as a library for embedding in other telemetry tools.
+
+mod cli;
+mod config;
+mod errors;
+mod identity;
+mod metrics;
+mod report;
+mod rpm_ostree;
+mod state;
+
+pub use cli::run;
+pub use config::inputs::{CollectingInput, InvalidLevelError};
+pub use errors::PingerError;
+pub use identity::{AlephError, Identity, PlatformDetectionError};
+
+/// Collect the machine identity with the given collecting configuration.
+///
+/// ```
+/// use fedora_coreos_pinger::{collect, CollectingInput};
+///
+/// let cfg = CollectingInput {
+///     level: "none".to_string(),
+///     ..CollectingInput::default()
+/// };
+/// let id = collect(&cfg).unwrap();
+/// assert!(id.get_data().is_empty());
+/// ```
+pub fn collect(cfg: &CollectingInput) -> Result<Identity, PingerError> {
+    let id = Identity::new(cfg)?;
+    Ok(id)
+}
@@ -0,0 +1,319 @@
+//! Prometheus text-format metrics for the collected identity.
+
+use crate::identity::{CollectingLevel, Identity};
+use failure::{Fallible, ResultExt};
+use log::warn;
+use std::fmt::Write;
+use std::io::{BufRead, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::time::Duration;
+
+/// Name of the info gauge carrying the identity labels.
+const INFO_METRIC: &str = "fedora_coreos_pinger_info";
+/// Name of the gauge carrying the number of online CPUs.
+const CPUS_METRIC: &str = "fedora_coreos_pinger_cpus";
+/// Name of the gauge carrying the total memory.
+const MEMORY_METRIC: &str = "fedora_coreos_pinger_memory_bytes";
+/// Path metrics are served on.
+const METRICS_PATH: &str = "/metrics";
+/// Content type of the Prometheus text format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/// Timeout for reading a scrape request.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Render the collected identity as Prometheus text-format metrics.
+///
+/// The identity is exposed as an info-style gauge, with the interesting
+/// values as labels and a constant value of `1`. Numeric values collected at
+/// the `"full"` level are exposed as their own gauges.
+///
+/// Only the reported data (see `Identity::get_data`) is exposed: values which
+/// were not collected, or are not reported, are left out. Nothing at all is
+/// exposed at the `"none"` level.
+pub(crate) fn render(id: &Identity) -> String {
+    if id.level == CollectingLevel::None {
+        return String::new();
+    }
+
+    let data = id.get_data();
+    let version = data
+        .get("current_os_version")
+        .or_else(|| data.get("original_os_version"));
+    let labels = [
+        ("platform", data.get("platform")),
+        ("stream", data.get("stream")),
+        ("architecture", data.get("architecture")),
+        ("version", version),
+    ];
+    let labels: Vec<String> = labels
+        .iter()
+        .filter_map(|(name, value)| {
+            let value = (*value)?;
+            Some(format!("{}=\"{}\"", name, escape_label_value(value)))
+        })
+        .collect();
+
+    let mut metrics = format!(
+        "# HELP {name} Fedora CoreOS pinger identity.\n# TYPE {name} gauge\n{name}{{{labels}}} 1\n",
+        name = INFO_METRIC,
+        labels = labels.join(",")
+    );
+    let gauge_value = |name: &str| data.get(name).and_then(|value| value.parse::<u64>().ok());
+    let gauges = [
+        (
+            CPUS_METRIC,
+            "Number of online CPUs.",
+            gauge_value("cpu_count"),
+        ),
+        (
+            MEMORY_METRIC,
+            "Total memory in bytes.",
+            gauge_value("total_memory_kb").map(|kb| kb * 1024),
+        ),
+    ];
+    for (name, help, value) in gauges.iter() {
+        if let Some(value) = value {
+            let _ = write!(
+                metrics,
+                "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n",
+                name = name,
+                help = help,
+                value = value
+            );
+        }
+    }
+
+    metrics
+}
+
+/// Atomically write the metrics for the given identity to a file, for the
+/// node-exporter textfile collector.
+pub(crate) fn write_textfile(id: &Identity, path: &Path) -> Fallible<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .context(format!("failed to create directory '{}'", dir.display()))?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, render(id))
+        .context(format!("failed to write '{}'", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .context(format!("failed to rename '{}'", tmp_path.display()))?;
+    Ok(())
+}
+
+/// Serve metrics over HTTP on the given listener, for Prometheus to scrape.
+///
+/// Metrics are rendered by `render_metrics` on each `GET /metrics` request,
+/// so that they are up-to-date. Only fails if accepting a connection fails.
+pub(crate) fn serve<F>(listener: &TcpListener, render_metrics: F) -> Fallible<()>
+where
+    F: Fn() -> Fallible<String>,
+{
+    for stream in listener.incoming() {
+        let stream = stream.context("failed to accept metrics connection")?;
+        if let Err(e) = handle_scrape(stream, &render_metrics) {
+            warn!("failed to serve metrics: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Answer a single scrape request.
+fn handle_scrape<F>(mut stream: TcpStream, render_metrics: &F) -> Fallible<()>
+where
+    F: Fn() -> Fallible<String>,
+{
+    stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Headers are not used, but must be read before answering.
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let request: Vec<&str> = request_line.split_whitespace().collect();
+    let (status, body) = match request.as_slice() {
+        ["GET", METRICS_PATH, ..] => match render_metrics() {
+            Ok(metrics) => ("200 OK", metrics),
+            Err(e) => {
+                warn!("failed to render metrics: {}", e);
+                ("500 Internal Server Error", String::new())
+            }
+        },
+        ["GET", ..] => ("404 Not Found", String::new()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    );
+    std::io::Write::write_all(&mut stream, response.as_bytes())?;
+    Ok(())
+}
+
+/// Escape a label value as required by the Prometheus text format.
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Platform;
+
+    #[test]
+    fn test_render() {
+        let id = Identity::mock_default("minimal");
+        let metrics = render(&id);
+
+        assert!(metrics.contains("# TYPE fedora_coreos_pinger_info gauge\n"));
+        assert!(
+            metrics.contains(
+                "fedora_coreos_pinger_info{platform=\"mock-platform\",stream=\"testing\",architecture=\"x86_64\",version=\"30.20190923.dev.2-2\"} 1\n"
+            ),
+            "{}",
+            metrics
+        );
+    }
+
+    #[test]
+    fn test_render_escaped() {
+        let id = Identity {
+            platform: Platform::from_id("a\"b\\c\nd"),
+            stream: None,
+            current_os_version: None,
+            ..Identity::mock_default("minimal")
+        };
+        let metrics = render(&id);
+
+        assert!(
+            metrics.contains(
+                "fedora_coreos_pinger_info{platform=\"a\\\"b\\\\c\\nd\",architecture=\"x86_64\",version=\"30.20190923.dev.2-2\"} 1\n"
+            ),
+            "{}",
+            metrics
+        );
+    }
+
+    #[test]
+    fn test_render_reported_fields() {
+        assert_eq!(render(&Identity::mock_default("none")), "");
+
+        let id = Identity {
+            exclude_fields: vec!["stream".to_string(), "cpu_count".to_string()],
+            ..Identity::mock_default("full")
+        };
+        let metrics = render(&id);
+        assert!(
+            metrics.contains(
+                "fedora_coreos_pinger_info{platform=\"mock-platform\",architecture=\"x86_64\",version=\"30.20190923.dev.2-2\"} 1\n"
+            ),
+            "{}",
+            metrics
+        );
+        assert!(!metrics.contains(CPUS_METRIC), "{}", metrics);
+        assert!(metrics.contains(MEMORY_METRIC), "{}", metrics);
+
+        let id = Identity {
+            report_fields: Some(vec!["platform".to_string(), "cpu_count".to_string()]),
+            ..Identity::mock_default("full")
+        };
+        let metrics = render(&id);
+        assert!(
+            metrics.contains("\nfedora_coreos_pinger_info{platform=\"mock-platform\"} 1\n"),
+            "{}",
+            metrics
+        );
+        assert!(metrics.contains(CPUS_METRIC), "{}", metrics);
+        assert!(!metrics.contains(MEMORY_METRIC), "{}", metrics);
+    }
+
+    #[test]
+    fn test_render_gauges() {
+        let metrics = render(&Identity::mock_default("minimal"));
+        assert!(!metrics.contains(CPUS_METRIC), "{}", metrics);
+        assert!(!metrics.contains(MEMORY_METRIC), "{}", metrics);
+
+        let metrics = render(&Identity::mock_default("full"));
+        assert!(metrics
+            .contains("# TYPE fedora_coreos_pinger_cpus gauge\nfedora_coreos_pinger_cpus 4\n"));
+        assert!(metrics.contains(
+            "# TYPE fedora_coreos_pinger_memory_bytes gauge\nfedora_coreos_pinger_memory_bytes 4122980352\n"
+        ));
+    }
+
+    #[test]
+    fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            serve(&listener, || Ok(render(&Identity::mock_default("full")))).unwrap()
+        });
+
+        let url = format!("http://{}{}", addr, METRICS_PATH);
+        let resp = reqwest::blocking::get(&url).unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], CONTENT_TYPE);
+        assert_eq!(
+            resp.text().unwrap(),
+            render(&Identity::mock_default("full"))
+        );
+
+        let resp = reqwest::blocking::get(format!("http://{}/", addr)).unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_write_textfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("textfile/fcos-pinger.prom");
+        let id = Identity::mock_default("full");
+        write_textfile(&id, &path).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, render(&id));
+        assert!(!path.with_extension("tmp").exists());
+        for line in contents.lines() {
+            if line.starts_with('#') {
+                assert!(
+                    line.starts_with("# HELP fedora_coreos_pinger_")
+                        || line.starts_with("# TYPE fedora_coreos_pinger_"),
+                    "{}",
+                    line
+                );
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            assert!(series.starts_with("fedora_coreos_pinger_"), "{}", line);
+            value.parse::<f64>().unwrap();
+        }
+
+        // Fields which are not reported are left out of the file too.
+        let id = Identity {
+            exclude_fields: vec!["platform".to_string(), "total_memory_kb".to_string()],
+            ..Identity::mock_default("full")
+        };
+        write_textfile(&id, &path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("platform="), "{}", contents);
+        assert!(!contents.contains(MEMORY_METRIC), "{}", contents);
+        assert!(contents.contains(CPUS_METRIC), "{}", contents);
+    }
+}
@@ -0,0 +1,1091 @@
+//! Reporting of the collected identity to a remote endpoint.
+
+pub(crate) mod statsd;
+
+use crate::config::inputs;
+use crate::identity::{Format, Identity};
+use failure::{bail, Fallible, ResultExt};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::warn;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// User-Agent of report requests, identifying the pinger and its version.
+const USER_AGENT: &str = concat!("fedora-coreos-pinger/", env!("CARGO_PKG_VERSION"));
+
+/// Environment variables holding a proxy URL, by decreasing precedence.
+const PROXY_ENV_VARS: &[&str] = &["https_proxy", "HTTPS_PROXY", "http_proxy", "HTTP_PROXY"];
+
+/// Environment variables listing hosts not to proxy, by decreasing precedence.
+const NO_PROXY_ENV_VARS: &[&str] = &["no_proxy", "NO_PROXY"];
+
+/// Upper bound of the backoff delay before a retry, jitter excluded.
+const MAX_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// Error returned when the endpoint rejects a report with a client error (4xx).
+#[derive(Debug)]
+pub(crate) struct RejectedError {
+    pub(crate) endpoint: String,
+    pub(crate) status: u16,
+}
+
+impl fmt::Display for RejectedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "report to '{}' rejected with status {}",
+            self.endpoint, self.status
+        )
+    }
+}
+
+impl std::error::Error for RejectedError {}
+
+/// Error returned when the endpoint is overloaded or rate-limiting (429 or 503).
+#[derive(Debug)]
+pub(crate) struct ThrottledError {
+    pub(crate) endpoint: String,
+    pub(crate) status: u16,
+    /// Delay requested by the `Retry-After` header, if any.
+    pub(crate) retry_after: Option<Duration>,
+}
+
+impl fmt::Display for ThrottledError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "report to '{}' throttled with status {}",
+            self.endpoint, self.status
+        )
+    }
+}
+
+impl std::error::Error for ThrottledError {}
+
+/// HTTP client submitting identity reports, retrying on transient failures.
+pub(crate) struct Reporter {
+    client: reqwest::blocking::Client,
+    format: Format,
+    max_retries: u32,
+    base_backoff: Duration,
+    /// Maximum honored `Retry-After` delay.
+    max_retry_after: Duration,
+    /// Size in bytes above which reports are gzip-compressed, if compression is enabled.
+    gzip_threshold: Option<usize>,
+    spool: Spool,
+}
+
+impl Reporter {
+    /// Create a reporter from the reporting configuration.
+    ///
+    /// Requests go through the configured proxy if any, otherwise through the
+    /// proxy set in the environment (`https_proxy`, `http_proxy`). Hosts
+    /// listed in `no_proxy` are reached directly.
+    pub(crate) fn new(cfg: &inputs::ReportingInput) -> Fallible<Self> {
+        Self::with_env(cfg, |var| std::env::var(var).ok())
+    }
+
+    /// Create a reporter, looking up proxy environment variables through `getenv`.
+    fn with_env<F>(cfg: &inputs::ReportingInput, getenv: F) -> Fallible<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut builder = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(cfg.timeout_secs))
+            .user_agent(USER_AGENT);
+        builder = match proxy_url(cfg.proxy.as_deref(), &getenv) {
+            Some(url) => {
+                let no_proxy =
+                    no_proxy_hosts(&getenv).and_then(|h| reqwest::NoProxy::from_string(&h));
+                let proxy = reqwest::Proxy::all(&url)
+                    .context(format!("invalid proxy URL '{}'", url))?
+                    .no_proxy(no_proxy);
+                builder.proxy(proxy)
+            }
+            None => builder.no_proxy(),
+        };
+        let client = builder.build().context("failed to build HTTP client")?;
+
+        // The format is checked when validating the config.
+        let format = Format::parse(&cfg.format).unwrap_or(Format::Json);
+
+        Ok(Self {
+            client,
+            format,
+            max_retries: cfg.max_retries,
+            base_backoff: Duration::from_secs(cfg.base_backoff_secs),
+            max_retry_after: Duration::from_secs(cfg.max_retry_after_secs),
+            gzip_threshold: Some(cfg.gzip_threshold_bytes).filter(|_| cfg.compress),
+            spool: Spool {
+                dir: PathBuf::from(&cfg.spool_dir),
+                max_files: cfg.spool_max_files,
+            },
+        })
+    }
+
+    /// Submit the collected identity to the given endpoint, in the configured format.
+    ///
+    /// Connection errors and server errors (5xx) are retried with exponential
+    /// backoff and jitter, while a rejection (4xx) fails immediately with `RejectedError`.
+    /// When throttled (429 or 503), the delay requested by the endpoint through
+    /// `Retry-After` is honored instead, up to the configured maximum.
+    /// If all retries fail, the report is spooled to disk to be sent again by
+    /// `flush_spool` on a later run.
+    pub(crate) fn submit(&self, endpoint: &str, identity: &Identity) -> Fallible<()> {
+        let body = identity.serialize(self.format)?;
+        let mut retries = 0;
+        loop {
+            let err = match self.post(endpoint, &body, self.format) {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if err.downcast_ref::<RejectedError>().is_some() {
+                return Err(err);
+            }
+            if retries >= self.max_retries {
+                match self.spool.write(&body, self.format) {
+                    Ok(Some(path)) => warn!("report spooled to '{}'", path.display()),
+                    Ok(None) => {}
+                    Err(e) => warn!("failed to spool report: {}", e),
+                }
+                return Err(err);
+            }
+
+            let delay = retry_delay(&err, self.max_retry_after, self.base_backoff, retries);
+            warn!(
+                "{}, retrying in {}s",
+                err.find_root_cause(),
+                delay.as_secs_f64()
+            );
+            std::thread::sleep(delay);
+            retries += 1;
+        }
+    }
+
+    /// Send spooled reports to the given endpoint, oldest first.
+    ///
+    /// Reports are sent in the format they were spooled in, and are removed
+    /// from the spool once sent, or once rejected by the
+    /// endpoint. Flushing stops at the first transient failure, leaving the
+    /// remaining reports for a later run.
+    pub(crate) fn flush_spool(&self, endpoint: &str) -> Fallible<()> {
+        for path in self.spool.files()? {
+            let body = std::fs::read(&path).context(format!(
+                "failed to read spooled report '{}'",
+                path.display()
+            ))?;
+            match self.post(endpoint, &body, spooled_format(&path)) {
+                Ok(()) => {}
+                Err(e) => match e.downcast::<RejectedError>() {
+                    Ok(rejected) => warn!("dropping spooled report: {}", rejected),
+                    Err(e) => return Err(e),
+                },
+            }
+            std::fs::remove_file(&path).context(format!(
+                "failed to remove spooled report '{}'",
+                path.display()
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Send a document in the given format to the given endpoint, once.
+    ///
+    /// Documents larger than the gzip threshold are sent compressed, unless
+    /// compression is disabled.
+    fn post(&self, endpoint: &str, body: &[u8], format: Format) -> Fallible<()> {
+        let mut request = self
+            .client
+            .post(endpoint)
+            .header(CONTENT_TYPE, format.content_type());
+        request = match self.gzip_threshold {
+            Some(threshold) if body.len() > threshold => {
+                request.header(CONTENT_ENCODING, "gzip").body(gzip(body)?)
+            }
+            _ => request.body(body.to_vec()),
+        };
+        let resp = request
+            .send()
+            .context(format!("failed to send report to '{}'", endpoint))?;
+
+        let status = resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| parse_retry_after(v, SystemTime::now()));
+            return Err(ThrottledError {
+                endpoint: endpoint.to_string(),
+                status: status.as_u16(),
+                retry_after,
+            }
+            .into());
+        }
+        if status.is_client_error() {
+            return Err(RejectedError {
+                endpoint: endpoint.to_string(),
+                status: status.as_u16(),
+            }
+            .into());
+        }
+        if !status.is_success() {
+            bail!("report to '{}' failed with status {}", endpoint, status);
+        }
+
+        Ok(())
+    }
+}
+
+/// On-disk spool of reports which could not be sent.
+struct Spool {
+    dir: PathBuf,
+    /// Maximum number of spooled reports, spooling is disabled if zero.
+    max_files: usize,
+}
+
+impl Spool {
+    /// Write a report to the spool, dropping the oldest reports beyond the cap.
+    ///
+    /// Return the path of the spooled report, or `None` if spooling is disabled.
+    fn write(&self, body: &[u8], format: Format) -> Fallible<Option<PathBuf>> {
+        if self.max_files == 0 {
+            return Ok(None);
+        }
+
+        std::fs::create_dir_all(&self.dir).context(format!(
+            "failed to create spool directory '{}'",
+            self.dir.display()
+        ))?;
+        // Zero-padded timestamps keep file names in chronological order.
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let path = self
+            .dir
+            .join(format!("report-{:020}.{}", timestamp, format.extension()));
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, body)
+            .context(format!("failed to write '{}'", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &path)
+            .context(format!("failed to rename '{}'", tmp_path.display()))?;
+
+        let files = self.files()?;
+        let excess = files.len().saturating_sub(self.max_files);
+        for old in &files[..excess] {
+            warn!("dropping spooled report '{}'", old.display());
+            std::fs::remove_file(old).context(format!(
+                "failed to remove spooled report '{}'",
+                old.display()
+            ))?;
+        }
+
+        Ok(Some(path))
+    }
+
+    /// Return the spooled reports, oldest first.
+    fn files(&self) -> Fallible<Vec<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut files = vec![];
+        let entries = std::fs::read_dir(&self.dir).context(format!(
+            "failed to read spool directory '{}'",
+            self.dir.display()
+        ))?;
+        for entry in entries {
+            let path = entry?.path();
+            if is_spooled_report(&path) {
+                files.push(path);
+            }
+        }
+        files.sort();
+
+        Ok(files)
+    }
+}
+
+/// Atomically write the JSON report for the given identity to a file, e.g.
+/// to be collected and sent from another machine in air-gapped environments.
+///
+/// Missing parent directories are created, and the file is readable by all.
+pub(crate) fn write_to_file(path: &Path, id: &Identity) -> Fallible<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .context(format!("failed to create directory '{}'", dir.display()))?;
+    }
+    let body = id.to_json()?;
+    let tmp_path = path.with_extension("tmp");
+    let result = write_report_file(&tmp_path, path, &body);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Write `body` to `tmp_path` with mode 0644, then move it to `path`.
+fn write_report_file(tmp_path: &Path, path: &Path, body: &str) -> Fallible<()> {
+    std::fs::write(tmp_path, body).context(format!("failed to write '{}'", tmp_path.display()))?;
+    std::fs::set_permissions(tmp_path, std::fs::Permissions::from_mode(0o644)).context(format!(
+        "failed to set permissions on '{}'",
+        tmp_path.display()
+    ))?;
+    std::fs::rename(tmp_path, path)
+        .context(format!("failed to rename '{}'", tmp_path.display()))?;
+    Ok(())
+}
+
+/// Compress the given document with gzip.
+fn gzip(body: &[u8]) -> Fallible<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder
+        .write_all(body)
+        .context("failed to compress report")?;
+    let compressed = encoder.finish().context("failed to compress report")?;
+    Ok(compressed)
+}
+
+/// Whether the given path is a spooled report.
+fn is_spooled_report(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    name.starts_with("report-") && Format::from_extension(extension).is_some()
+}
+
+/// Return the format of a spooled report, from its extension.
+fn spooled_format(path: &Path) -> Format {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .and_then(Format::from_extension)
+        .unwrap_or(Format::Json)
+}
+
+/// Return the proxy URL to use, preferring the configured one over the
+/// environment variables looked up through `getenv`.
+fn proxy_url<F>(configured: Option<&str>, getenv: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if let Some(url) = configured {
+        return Some(url.to_string());
+    }
+    PROXY_ENV_VARS
+        .iter()
+        .filter_map(|var| getenv(var))
+        .find(|url| !url.trim().is_empty())
+}
+
+/// Return the hosts not to proxy, from the environment variables looked up
+/// through `getenv`.
+fn no_proxy_hosts<F>(getenv: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    NO_PROXY_ENV_VARS
+        .iter()
+        .filter_map(|var| getenv(var))
+        .find(|hosts| !hosts.trim().is_empty())
+}
+
+/// Parse a `Retry-After` header value, either a number of seconds or an HTTP
+/// date, into a delay from `now`. A date in the past means no delay.
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+/// Compute the delay before retrying after the given error, honoring the
+/// `Retry-After` delay of a throttled request up to `max_retry_after`, and
+/// falling back to `backoff_delay` otherwise.
+fn retry_delay(
+    err: &failure::Error,
+    max_retry_after: Duration,
+    base: Duration,
+    retry: u32,
+) -> Duration {
+    match err.downcast_ref::<ThrottledError>() {
+        Some(ThrottledError {
+            retry_after: Some(retry_after),
+            ..
+        }) => (*retry_after).min(max_retry_after),
+        _ => backoff_delay(base, retry),
+    }
+}
+
+/// Compute the delay before the given retry (starting at 0), as an
+/// exponential backoff capped at `MAX_BACKOFF`, with up to 50% of random
+/// jitter added.
+fn backoff_delay(base: Duration, retry: u32) -> Duration {
+    let delay = base
+        .saturating_mul(2u32.saturating_pow(retry))
+        .min(MAX_BACKOFF);
+    let jitter = rand::rng().random_range(0.0..=0.5);
+    delay.mul_f64(1.0 + jitter)
+}
+
+/// Compute a random delay before reporting, uniformly distributed in `[0, max]`.
+pub(crate) fn compute_jitter(max: Duration) -> Duration {
+    compute_jitter_with(max, &mut rand::rng())
+}
+
+/// Compute the startup delay for the given config, reproducible if
+/// `jitter_seed` is set.
+pub(crate) fn startup_jitter(cfg: &inputs::ReportingInput) -> Duration {
+    let max = Duration::from_secs(cfg.max_jitter_secs);
+    match cfg.jitter_seed {
+        Some(seed) => compute_jitter_with(max, &mut StdRng::seed_from_u64(seed)),
+        None => compute_jitter(max),
+    }
+}
+
+/// Compute a random delay in `[0, max]` from the given generator.
+fn compute_jitter_with<R: Rng>(max: Duration, rng: &mut R) -> Duration {
+    let max_nanos = u64::try_from(max.as_nanos()).unwrap_or(u64::MAX);
+    Duration::from_nanos(rng.random_range(0..=max_nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+    use std::io::Read;
+
+    /// Return a reporter spooling reports into the given directory.
+    fn spooling_reporter(dir: &Path, max_files: usize) -> Reporter {
+        let cfg = inputs::ReportingInput {
+            spool_dir: dir.to_str().unwrap().to_string(),
+            spool_max_files: max_files,
+            max_retries: 1,
+            ..inputs::ReportingInput::mock()
+        };
+        Reporter::new(&cfg).unwrap()
+    }
+
+    fn mock_reporter(max_retries: u32) -> Reporter {
+        let cfg = inputs::ReportingInput {
+            max_retries,
+            ..inputs::ReportingInput::mock()
+        };
+        Reporter::new(&cfg).unwrap()
+    }
+
+    /// Send the collected identity in the configured format to the given endpoint, once.
+    fn send(reporter: &Reporter, endpoint: &str, identity: &Identity) -> Fallible<()> {
+        reporter.post(
+            endpoint,
+            &identity.serialize(reporter.format)?,
+            reporter.format,
+        )
+    }
+
+    #[test]
+    fn test_send() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/report")
+            .match_header("content-type", "application/json")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "level": "full",
+                "platform": "mock-platform",
+                "instance_type": "mock-instance-type",
+            })))
+            .with_status(200)
+            .create();
+
+        let id = Identity::mock_default("full");
+        let endpoint = format!("{}/v1/report", server.url());
+        send(&mock_reporter(3), &endpoint, &id).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_user_agent() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header(
+                "user-agent",
+                format!("fedora-coreos-pinger/{}", env!("CARGO_PKG_VERSION")).as_str(),
+            )
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let id = Identity::mock_default("minimal");
+        send(&mock_reporter(0), &server.url(), &id).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_msgpack() {
+        let id = Identity::mock_default("full");
+        let expected = id.to_json_value().unwrap();
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-type", "application/msgpack")
+            .match_request(move |request| {
+                let body = request.body().unwrap();
+                rmp_serde::from_slice::<serde_json::Value>(body)
+                    .map(|value| value == expected)
+                    .unwrap_or(false)
+            })
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let cfg = inputs::ReportingInput {
+            format: "msgpack".to_string(),
+            ..inputs::ReportingInput::mock()
+        };
+        let reporter = Reporter::new(&cfg).unwrap();
+        send(&reporter, &server.url(), &id).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_gzip() {
+        let id = Identity::mock_default("full");
+        let expected = id.to_json().unwrap();
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-type", "application/json")
+            .match_header("content-encoding", "gzip")
+            .match_request(move |request| {
+                let mut decoded = String::new();
+                let body = request.body().unwrap();
+                flate2::read::GzDecoder::new(body.as_slice())
+                    .read_to_string(&mut decoded)
+                    .is_ok()
+                    && decoded == expected
+            })
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let cfg = inputs::ReportingInput {
+            gzip_threshold_bytes: 512,
+            ..inputs::ReportingInput::mock()
+        };
+        let reporter = Reporter::new(&cfg).unwrap();
+        send(&reporter, &server.url(), &id).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_small_uncompressed() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-encoding", Matcher::Missing)
+            .match_body(Matcher::PartialJson(
+                serde_json::json!({"level": "minimal"}),
+            ))
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let id = Identity::mock_default("minimal");
+        let cfg = inputs::ReportingInput {
+            gzip_threshold_bytes: id.to_json().unwrap().len(),
+            ..inputs::ReportingInput::mock()
+        };
+        let reporter = Reporter::new(&cfg).unwrap();
+        send(&reporter, &server.url(), &id).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_compression_disabled() {
+        let id = Identity::mock_default("full");
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-encoding", Matcher::Missing)
+            .match_body(id.to_json().unwrap().as_str())
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let cfg = inputs::ReportingInput {
+            compress: false,
+            gzip_threshold_bytes: 0,
+            ..inputs::ReportingInput::mock()
+        };
+        let reporter = Reporter::new(&cfg).unwrap();
+        send(&reporter, &server.url(), &id).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_error_status() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/").with_status(503).create();
+
+        let id = Identity::mock_default("minimal");
+        let err = send(&mock_reporter(3), &server.url(), &id).unwrap_err();
+        assert!(err.to_string().contains("503"), "unexpected error: {}", err);
+        mock.assert();
+    }
+
+    #[test]
+    fn test_submit_retry() {
+        let mut server = mockito::Server::new();
+        let failing = server.mock("POST", "/").with_status(503).expect(2).create();
+        let succeeding = server.mock("POST", "/").with_status(200).expect(1).create();
+
+        let id = Identity::mock_default("minimal");
+        mock_reporter(3).submit(&server.url(), &id).unwrap();
+        failing.assert();
+        succeeding.assert();
+    }
+
+    #[test]
+    fn test_submit_throttled() {
+        let mut server = mockito::Server::new();
+        let throttled = server
+            .mock("POST", "/")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .expect(1)
+            .create();
+        let succeeding = server.mock("POST", "/").with_status(200).expect(1).create();
+
+        let id = Identity::mock_default("minimal");
+        mock_reporter(3).submit(&server.url(), &id).unwrap();
+        throttled.assert();
+        succeeding.assert();
+    }
+
+    #[test]
+    fn test_submit_throttled_capped() {
+        // Retry-After is capped by `max_retry_after_secs`, here 0.
+        let mut server = mockito::Server::new();
+        let numeric = server
+            .mock("POST", "/")
+            .with_status(429)
+            .with_header("retry-after", "3600")
+            .expect(1)
+            .create();
+        let date = server
+            .mock("POST", "/")
+            .with_status(429)
+            .with_header("retry-after", "Fri, 31 Dec 2100 23:59:59 GMT")
+            .expect(1)
+            .create();
+        let succeeding = server.mock("POST", "/").with_status(200).expect(1).create();
+
+        let id = Identity::mock_default("minimal");
+        mock_reporter(3).submit(&server.url(), &id).unwrap();
+        numeric.assert();
+        date.assert();
+        succeeding.assert();
+    }
+
+    #[test]
+    fn test_send_throttled() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("POST", "/")
+            .with_status(503)
+            .with_header("retry-after", "120")
+            .create();
+
+        let id = Identity::mock_default("minimal");
+        let err = send(&mock_reporter(0), &server.url(), &id).unwrap_err();
+        let throttled = err.downcast_ref::<ThrottledError>().unwrap();
+        assert_eq!(throttled.status, 503);
+        assert_eq!(throttled.retry_after, Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::from_secs(0)));
+        // 784111777 is Sun, 06 Nov 1994 08:49:37 GMT.
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:51:37 GMT", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:48:37 GMT", now),
+            Some(Duration::from_secs(0))
+        );
+        assert_eq!(parse_retry_after("-1", now), None);
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let throttled = |retry_after| -> failure::Error {
+            ThrottledError {
+                endpoint: "http://pinger.invalid".to_string(),
+                status: 429,
+                retry_after,
+            }
+            .into()
+        };
+        let max = Duration::from_secs(3600);
+        let base = Duration::from_secs(0);
+        assert_eq!(
+            retry_delay(&throttled(Some(Duration::from_secs(30))), max, base, 0),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            retry_delay(&throttled(Some(Duration::from_secs(86400))), max, base, 0),
+            max
+        );
+        // Without Retry-After, or on other errors, fall back to the backoff.
+        assert_eq!(
+            retry_delay(&throttled(None), max, base, 2),
+            Duration::from_secs(0)
+        );
+        let err = failure::err_msg("connection refused");
+        assert_eq!(retry_delay(&err, max, base, 2), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let base = Duration::from_secs(1);
+        for retry in 0..5 {
+            let expected = base * 2u32.pow(retry);
+            let delay = backoff_delay(base, retry);
+            assert!(delay >= expected, "delay {:?} for retry {}", delay, retry);
+            assert!(
+                delay <= expected.mul_f64(1.5),
+                "delay {:?} for retry {}",
+                delay,
+                retry
+            );
+        }
+        assert_eq!(
+            backoff_delay(Duration::from_secs(0), 3),
+            Duration::from_secs(0)
+        );
+
+        // Large delays are capped instead of overflowing.
+        let delay = backoff_delay(Duration::from_secs(u64::MAX), u32::MAX);
+        assert!(delay >= MAX_BACKOFF);
+        assert!(delay <= MAX_BACKOFF.mul_f64(1.5));
+    }
+
+    #[test]
+    fn test_compute_jitter() {
+        let max = Duration::from_secs(30);
+        for _ in 0..1000 {
+            let jitter = compute_jitter(max);
+            assert!(jitter <= max, "jitter {:?} above {:?}", jitter, max);
+        }
+        assert_eq!(
+            compute_jitter(Duration::from_secs(0)),
+            Duration::from_secs(0)
+        );
+    }
+
+    #[test]
+    fn test_startup_jitter_seeded() {
+        let cfg = inputs::ReportingInput {
+            max_jitter_secs: 600,
+            jitter_seed: Some(42),
+            ..inputs::ReportingInput::mock()
+        };
+        let jitter = startup_jitter(&cfg);
+        assert!(jitter <= Duration::from_secs(600));
+        assert_eq!(jitter, startup_jitter(&cfg));
+
+        let cfg = inputs::ReportingInput {
+            max_jitter_secs: 0,
+            ..cfg
+        };
+        assert_eq!(startup_jitter(&cfg), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_submit_retries_exhausted() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/").with_status(500).expect(4).create();
+
+        let id = Identity::mock_default("minimal");
+        let err = mock_reporter(3).submit(&server.url(), &id).unwrap_err();
+        assert!(err.to_string().contains("500"), "unexpected error: {}", err);
+        mock.assert();
+    }
+
+    #[test]
+    fn test_submit_rejected() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/").with_status(400).expect(1).create();
+
+        let id = Identity::mock_default("minimal");
+        let err = mock_reporter(3).submit(&server.url(), &id).unwrap_err();
+        let rejected = err.downcast_ref::<RejectedError>().unwrap();
+        assert_eq!(rejected.status, 400);
+        mock.assert();
+    }
+
+    #[test]
+    fn test_proxy_url() {
+        let env = |var: &str| match var {
+            "HTTPS_PROXY" => Some("http://env-proxy:3128".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            proxy_url(Some("http://config-proxy:8080"), env),
+            Some("http://config-proxy:8080".to_string())
+        );
+        assert_eq!(
+            proxy_url(None, env),
+            Some("http://env-proxy:3128".to_string())
+        );
+        assert_eq!(proxy_url(None, |_| None), None);
+        assert_eq!(proxy_url(None, |_| Some(String::new())), None);
+    }
+
+    #[test]
+    fn test_submit_through_proxy() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/v1/report").with_status(200).create();
+
+        let cfg = inputs::ReportingInput {
+            proxy: Some(server.url()),
+            ..inputs::ReportingInput::mock()
+        };
+        let id = Identity::mock_default("minimal");
+        Reporter::new(&cfg)
+            .unwrap()
+            .submit("http://pinger.invalid/v1/report", &id)
+            .unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_submit_through_env_proxy() {
+        let mut proxy = mockito::Server::new();
+        let proxied = proxy.mock("POST", "/v1/report").with_status(200).create();
+        let proxy_url = proxy.url();
+        let env = |var: &str| match var {
+            "HTTPS_PROXY" | "HTTP_PROXY" => Some(proxy_url.clone()),
+            _ => None,
+        };
+
+        let id = Identity::mock_default("minimal");
+        Reporter::with_env(&inputs::ReportingInput::mock(), env)
+            .unwrap()
+            .submit("http://pinger.invalid/v1/report", &id)
+            .unwrap();
+        proxied.assert();
+    }
+
+    #[test]
+    fn test_submit_no_proxy() {
+        let mut proxy = mockito::Server::new();
+        let proxied = proxy.mock("POST", "/").expect(0).create();
+        let mut server = mockito::Server::new();
+        let direct = server.mock("POST", "/").with_status(200).expect(1).create();
+        let proxy_url = proxy.url();
+        let env = |var: &str| match var {
+            "HTTP_PROXY" => Some(proxy_url.clone()),
+            "NO_PROXY" => Some("example.com, 127.0.0.1".to_string()),
+            _ => None,
+        };
+
+        let id = Identity::mock_default("minimal");
+        Reporter::with_env(&inputs::ReportingInput::mock(), env)
+            .unwrap()
+            .submit(&server.url(), &id)
+            .unwrap();
+        direct.assert();
+        proxied.assert();
+    }
+
+    #[test]
+    fn test_no_proxy_hosts() {
+        let env = |var: &str| match var {
+            "no_proxy" => Some(" ".to_string()),
+            "NO_PROXY" => Some("localhost,.example.com".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            no_proxy_hosts(env),
+            Some("localhost,.example.com".to_string())
+        );
+        assert_eq!(no_proxy_hosts(|_| None), None);
+    }
+
+    #[test]
+    fn test_submit_spooled() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/").with_status(503).expect(2).create();
+        let dir = tempfile::tempdir().unwrap();
+        let reporter = spooling_reporter(dir.path(), 10);
+
+        let id = Identity::mock_default("minimal");
+        reporter.submit(&server.url(), &id).unwrap_err();
+        mock.assert();
+
+        let files = reporter.spool.files().unwrap();
+        assert_eq!(files.len(), 1);
+        let spooled = std::fs::read_to_string(&files[0]).unwrap();
+        assert_eq!(spooled, id.to_json().unwrap());
+    }
+
+    #[test]
+    fn test_submit_rejected_not_spooled() {
+        let mut server = mockito::Server::new();
+        server.mock("POST", "/").with_status(400).create();
+        let dir = tempfile::tempdir().unwrap();
+        let reporter = spooling_reporter(dir.path(), 10);
+
+        let id = Identity::mock_default("minimal");
+        reporter.submit(&server.url(), &id).unwrap_err();
+        assert!(reporter.spool.files().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_spool_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let reporter = spooling_reporter(dir.path(), 2);
+
+        for body in &["first", "second", "third"] {
+            reporter
+                .spool
+                .write(body.as_bytes(), Format::Json)
+                .unwrap()
+                .unwrap();
+        }
+        let bodies: Vec<String> = reporter
+            .spool
+            .files()
+            .unwrap()
+            .iter()
+            .map(|f| std::fs::read_to_string(f).unwrap())
+            .collect();
+        assert_eq!(bodies, vec!["second", "third"]);
+    }
+
+    #[test]
+    fn test_flush_spool() {
+        let mut server = mockito::Server::new();
+        let first = server
+            .mock("POST", "/")
+            .match_body("first")
+            .with_status(200)
+            .expect(1)
+            .create();
+        let second = server
+            .mock("POST", "/")
+            .match_body("second")
+            .with_status(200)
+            .expect(1)
+            .create();
+        let dir = tempfile::tempdir().unwrap();
+        let reporter = spooling_reporter(dir.path(), 10);
+        reporter.spool.write(b"first", Format::Json).unwrap();
+        reporter.spool.write(b"second", Format::Json).unwrap();
+
+        reporter.flush_spool(&server.url()).unwrap();
+        first.assert();
+        second.assert();
+        assert!(reporter.spool.files().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_flush_spool_formats() {
+        let mut server = mockito::Server::new();
+        let json = server
+            .mock("POST", "/")
+            .match_header("content-type", "application/json")
+            .with_status(200)
+            .expect(1)
+            .create();
+        let msgpack = server
+            .mock("POST", "/")
+            .match_header("content-type", "application/msgpack")
+            .with_status(200)
+            .expect(1)
+            .create();
+        let dir = tempfile::tempdir().unwrap();
+        let reporter = spooling_reporter(dir.path(), 10);
+        reporter.spool.write(b"{}", Format::Json).unwrap();
+        reporter.spool.write(b"\x80", Format::MessagePack).unwrap();
+
+        reporter.flush_spool(&server.url()).unwrap();
+        json.assert();
+        msgpack.assert();
+        assert!(reporter.spool.files().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_flush_spool_failure() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/").with_status(503).expect(1).create();
+        let dir = tempfile::tempdir().unwrap();
+        let reporter = spooling_reporter(dir.path(), 10);
+        reporter.spool.write(b"first", Format::Json).unwrap();
+        reporter.spool.write(b"second", Format::Json).unwrap();
+
+        reporter.flush_spool(&server.url()).unwrap_err();
+        mock.assert();
+        assert_eq!(reporter.spool.files().unwrap().len(), 2);
+
+        // A missing spool directory has nothing to flush.
+        let reporter = spooling_reporter(&dir.path().join("missing"), 10);
+        reporter.flush_spool(&server.url()).unwrap();
+    }
+
+    #[test]
+    fn test_write_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reports/node/report.json");
+        let id = Identity::mock_default("full");
+        write_to_file(&path, &id).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, id.to_json().unwrap());
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn test_write_to_file_failure() {
+        // The target is a non-empty directory, so the final rename fails.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join("other"), "").unwrap();
+        let id = Identity::mock_default("full");
+        write_to_file(&path, &id).unwrap_err();
+
+        let entries: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec!["report.json"]);
+        assert!(path.is_dir());
+    }
+}
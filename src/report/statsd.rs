@@ -0,0 +1,144 @@
+//! Reporting of the collected identity as statsd metrics.
+
+use crate::identity::{CollectingLevel, Identity};
+use failure::{Fallible, ResultExt};
+use std::net::UdpSocket;
+
+/// Prefix of the emitted metric names.
+const METRIC_PREFIX: &str = "fcos_pinger";
+
+/// Send the collected identity as statsd gauges to the given `host:port`.
+///
+/// All metrics are sent in a single UDP datagram, tagged (DogStatsD-style)
+/// with the platform and stream. Numeric values collected at the `"full"`
+/// level are sent as their own gauges, next to a constant `info` gauge.
+///
+/// Nothing is sent at the `"none"` level.
+pub(crate) fn send_statsd(id: &Identity, host: &str) -> Fallible<()> {
+    if id.level == CollectingLevel::None {
+        return Ok(());
+    }
+
+    let socket = UdpSocket::bind("0.0.0.0:0").context("failed to bind statsd socket")?;
+    socket
+        .send_to(render(id).as_bytes(), host)
+        .context(format!("failed to send metrics to statsd host '{}'", host))?;
+    Ok(())
+}
+
+/// Render the statsd datagram for the given identity.
+///
+/// Only the reported data (see `Identity::get_data`) is sent: tags and gauges
+/// for values which were not collected, or are not reported, are left out.
+/// Nothing at all is rendered at the `"none"` level.
+fn render(id: &Identity) -> String {
+    if id.level == CollectingLevel::None {
+        return String::new();
+    }
+
+    let data = id.get_data();
+    let tags: Vec<String> = ["platform", "stream"]
+        .iter()
+        .filter_map(|name| {
+            let value = data.get(*name)?;
+            Some(format!("{}:{}", name, escape_tag_value(value)))
+        })
+        .collect();
+    let tags = if tags.is_empty() {
+        String::new()
+    } else {
+        format!("|#{}", tags.join(","))
+    };
+    let gauge_value = |name: &str| data.get(name).and_then(|value| value.parse::<u64>().ok());
+    let gauges = [
+        ("info", Some(1)),
+        ("cpus", gauge_value("cpu_count")),
+        ("memory_mb", gauge_value("memory_mb")),
+        ("uptime_seconds", gauge_value("uptime_seconds")),
+    ];
+    let lines: Vec<String> = gauges
+        .iter()
+        .filter_map(|(name, value)| {
+            let line = format!("{}.{}:{}|g{}", METRIC_PREFIX, name, value.as_ref()?, tags);
+            Some(line)
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Replace the characters delimiting statsd fields in a tag value.
+fn escape_tag_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            ',' | '|' | '#' | '\n' => '_',
+            _ => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Platform;
+    use std::time::Duration;
+
+    #[test]
+    fn test_send_statsd() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let host = server.local_addr().unwrap().to_string();
+
+        send_statsd(&Identity::mock_default("full"), &host).unwrap();
+        let mut buf = [0; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        let datagram = std::str::from_utf8(&buf[..len]).unwrap();
+        assert_eq!(
+            datagram,
+            "fcos_pinger.info:1|g|#platform:mock-platform,stream:testing\n\
+             fcos_pinger.cpus:4|g|#platform:mock-platform,stream:testing\n\
+             fcos_pinger.memory_mb:3931|g|#platform:mock-platform,stream:testing\n\
+             fcos_pinger.uptime_seconds:12346|g|#platform:mock-platform,stream:testing"
+        );
+    }
+
+    #[test]
+    fn test_render_minimal() {
+        let id = Identity {
+            platform: Platform::from_id("a,b|c#d"),
+            stream: None,
+            ..Identity::mock_default("minimal")
+        };
+        assert_eq!(render(&id), "fcos_pinger.info:1|g|#platform:a_b_c_d");
+        assert_eq!(render(&Identity::mock_default("none")), "");
+    }
+
+    #[test]
+    fn test_send_statsd_none() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let host = server.local_addr().unwrap().to_string();
+
+        send_statsd(&Identity::mock_default("none"), &host).unwrap();
+        let mut buf = [0; 1024];
+        server.recv(&mut buf).unwrap_err();
+    }
+
+    #[test]
+    fn test_render_reported_fields() {
+        let id = Identity {
+            exclude_fields: vec!["stream".to_string(), "memory_mb".to_string()],
+            ..Identity::mock_default("full")
+        };
+        assert_eq!(
+            render(&id),
+            "fcos_pinger.info:1|g|#platform:mock-platform\n\
+             fcos_pinger.cpus:4|g|#platform:mock-platform\n\
+             fcos_pinger.uptime_seconds:12346|g|#platform:mock-platform"
+        );
+    }
+}
@@ -0,0 +1,553 @@
+//! Interface to rpm-ostree.
+
+use failure::{bail, format_err, Fallible, ResultExt};
+use once_cell::unsync::OnceCell;
+use serde::Deserialize;
+use std::fmt;
+use std::io::{self, Read};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Error returned when `rpm-ostree status --json` lacks a required field.
+#[derive(Debug)]
+pub(crate) struct MissingFieldError {
+    pub(crate) field: &'static str,
+}
+
+impl fmt::Display for MissingFieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rpm-ostree status is missing field '{}'", self.field)
+    }
+}
+
+impl std::error::Error for MissingFieldError {}
+
+/// Error returned when the rpm-ostree binary is not installed, e.g. on hosts
+/// other than Fedora CoreOS.
+#[derive(Debug)]
+pub(crate) struct RpmOstreeUnavailable {
+    pub(crate) program: String,
+}
+
+impl fmt::Display for RpmOstreeUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "rpm-ostree is not available: '{}' not found",
+            self.program
+        )
+    }
+}
+
+impl std::error::Error for RpmOstreeUnavailable {}
+
+/// JSON output from `rpm-ostree status --json`.
+///
+/// Fields missing from older or newer rpm-ostree releases are defaulted,
+/// required fields are checked by `validate`.
+#[derive(Debug, Deserialize)]
+struct StatusJSON {
+    #[serde(default)]
+    deployments: Option<Vec<DeploymentJSON>>,
+}
+
+impl StatusJSON {
+    /// Check that all required fields are present.
+    fn validate(&self) -> Fallible<()> {
+        let deployments = self.deployments.as_ref().ok_or(MissingFieldError {
+            field: "deployments",
+        })?;
+        if deployments.iter().any(|d| d.checksum.is_empty()) {
+            return Err(MissingFieldError { field: "checksum" }.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Partial deployment object (only fields relevant to the pinger).
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct DeploymentJSON {
+    #[serde(default)]
+    pub(crate) booted: bool,
+    /// OSTree commit checksum (required).
+    #[serde(default)]
+    pub(crate) checksum: String,
+    /// Origin refspec, absent on deployments of a container image.
+    #[serde(default)]
+    pub(crate) origin: Option<String>,
+    /// Packages layered on the base commit.
+    #[serde(default)]
+    pub(crate) packages: Vec<String>,
+    #[serde(default, rename = "requested-packages")]
+    pub(crate) requested_packages: Vec<String>,
+    #[serde(default)]
+    pub(crate) staged: bool,
+    /// OS version, absent on deployments of a bare commit.
+    #[serde(default)]
+    pub(crate) version: Option<String>,
+}
+
+impl DeploymentJSON {
+    /// Return the update stream tracked by this deployment.
+    ///
+    /// Well-known Fedora CoreOS streams are returned by name, any other
+    /// origin refspec is returned as-is. Deployments pinned to a checksum
+    /// (without a branch) or to a container image do not track any stream.
+    pub(crate) fn stream(&self) -> Option<String> {
+        self.origin.as_deref().and_then(stream_from_refspec)
+    }
+}
+
+/// Map an origin refspec (e.g. `fedora:fedora/x86_64/coreos/stable`) to a stream name.
+fn stream_from_refspec(refspec: &str) -> Option<String> {
+    // Strip the remote name, if any.
+    let ostree_ref = refspec.splitn(2, ':').last().unwrap_or_default();
+    if is_checksum(ostree_ref) {
+        return None;
+    }
+
+    let parts: Vec<&str> = ostree_ref.split('/').collect();
+    let stream = match parts.as_slice() {
+        ["fedora", _, "coreos", stream] if ["stable", "testing", "next"].contains(stream) => {
+            stream.to_string()
+        }
+        _ => refspec.to_string(),
+    };
+    Some(stream)
+}
+
+/// Whether the given string is an OSTree commit checksum.
+fn is_checksum(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Handle to the rpm-ostree status, fetched at most once and then cached.
+///
+/// Failures are cached too, so that a missing or hanging rpm-ostree is only
+/// run once: the first access fails with the original error, and later ones
+/// with its message.
+pub(crate) struct Status {
+    runner: Box<dyn Fn() -> Fallible<Vec<u8>>>,
+    cached: OnceCell<Result<StatusJSON, String>>,
+}
+
+impl Status {
+    /// Create a handle which runs `rpm-ostree status --json` on first access,
+    /// failing if it does not complete within `timeout`.
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self::with_command("rpm-ostree", timeout)
+    }
+
+    /// Create a handle which runs `<program> status --json` on first access.
+    ///
+    /// If `program` cannot be found, accessors fail with `RpmOstreeUnavailable`.
+    pub(crate) fn with_command(program: &str, timeout: Duration) -> Self {
+        let program = program.to_string();
+        Self::with_runner(move || {
+            let mut cmd = Command::new(&program);
+            cmd.arg("status").arg("--json");
+            run_with_timeout(cmd, timeout).map_err(|e| {
+                let not_found = e
+                    .find_root_cause()
+                    .downcast_ref::<io::Error>()
+                    .map(io::Error::kind)
+                    == Some(io::ErrorKind::NotFound);
+                if not_found {
+                    RpmOstreeUnavailable {
+                        program: program.clone(),
+                    }
+                    .into()
+                } else {
+                    e
+                }
+            })
+        })
+    }
+
+    /// Create a handle which fetches the JSON status through the given runner.
+    pub(crate) fn with_runner<F>(runner: F) -> Self
+    where
+        F: Fn() -> Fallible<Vec<u8>> + 'static,
+    {
+        Self {
+            runner: Box::new(runner),
+            cached: OnceCell::new(),
+        }
+    }
+
+    /// Return the status, running the command if not cached yet.
+    fn status(&self) -> Fallible<&StatusJSON> {
+        if let Some(cached) = self.cached.get() {
+            return cached.as_ref().map_err(|e| format_err!("{}", e));
+        }
+
+        let status = match self.fetch() {
+            Ok(status) => status,
+            Err(e) => {
+                let message = e
+                    .iter_chain()
+                    .map(|cause| cause.to_string())
+                    .collect::<Vec<_>>()
+                    .join(": ");
+                let _ = self.cached.set(Err(message));
+                return Err(e);
+            }
+        };
+        let cached = self.cached.get_or_init(|| Ok(status));
+        Ok(cached.as_ref().expect("status cached as failed"))
+    }
+
+    /// Run the command and parse its status.
+    fn fetch(&self) -> Fallible<StatusJSON> {
+        let output = (self.runner)()?;
+        let status: StatusJSON =
+            serde_json::from_slice(&output).context("failed to parse rpm-ostree status")?;
+        status.validate()?;
+        Ok(status)
+    }
+
+    /// Return all deployments, in the order listed by rpm-ostree.
+    pub(crate) fn deployments(&self) -> Fallible<&[DeploymentJSON]> {
+        let deployments = self.status()?.deployments.as_deref().unwrap_or_default();
+        Ok(deployments)
+    }
+
+    /// Find the booted deployment.
+    pub(crate) fn booted(&self) -> Fallible<&DeploymentJSON> {
+        self.deployments()?
+            .iter()
+            .find(|d| d.booted)
+            .ok_or_else(|| format_err!("no booted deployment found"))
+    }
+
+    /// Return the sorted list of packages layered on the booted deployment.
+    pub(crate) fn layered_packages(&self) -> Fallible<Vec<String>> {
+        let mut packages = self.booted()?.requested_packages.clone();
+        packages.sort();
+        Ok(packages)
+    }
+
+    /// Whether the booted deployment has packages layered on its base commit.
+    pub(crate) fn has_layered_packages(&self) -> Fallible<bool> {
+        Ok(!self.booted()?.packages.is_empty())
+    }
+
+    /// Find the staged deployment, if any.
+    pub(crate) fn staged(&self) -> Fallible<Option<&DeploymentJSON>> {
+        Ok(self.deployments()?.iter().find(|d| d.staged))
+    }
+}
+
+/// Run the given command and return its output, killing it if it does not
+/// complete within `timeout`.
+fn run_with_timeout(mut cmd: Command, timeout: Duration) -> Fallible<Vec<u8>> {
+    let name = format!("{:?}", cmd);
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!("failed to run {}", name))?;
+
+    // Drain output in the background, so that the child never blocks on a full pipe.
+    let mut stdout = child.stdout.take().expect("missing stdout pipe");
+    let mut stderr = child.stderr.take().expect("missing stderr pipe");
+    let stdout_reader = std::thread::spawn(move || {
+        let mut buf = vec![];
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buf = vec![];
+        stderr.read_to_end(&mut buf).map(|_| buf)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            // Best-effort cleanup, the child may have exited in the meantime.
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("{} timed out after {}s", name, timeout.as_secs_f64());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    let stdout = stdout_reader
+        .join()
+        .map_err(|_| format_err!("failed to read stdout of {}", name))??;
+    let stderr = stderr_reader
+        .join()
+        .map_err(|_| format_err!("failed to read stderr of {}", name))??;
+
+    if !status.success() {
+        bail!("{} failed:\n{}", name, String::from_utf8_lossy(&stderr));
+    }
+
+    Ok(stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Return a runner serving the given fixture, and a counter of its invocations.
+    fn mock_runner(fixture: &'static str) -> (impl Fn() -> Fallible<Vec<u8>>, Rc<Cell<usize>>) {
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        let runner = move || {
+            counter.set(counter.get() + 1);
+            Ok(std::fs::read(fixture)?)
+        };
+        (runner, calls)
+    }
+
+    #[test]
+    fn test_booted() {
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status.json");
+        let status = Status::with_runner(runner);
+
+        let booted = status.booted().unwrap();
+        assert!(booted.booted);
+        assert_eq!(booted.version.as_deref(), Some("30.20190923.dev.2-2"));
+        assert_eq!(
+            booted.checksum,
+            "4b8d4e5a0f7e8a1a5f2c3e1f0b6b4a8e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a"
+        );
+        assert_eq!(booted.stream(), Some("testing".to_string()));
+    }
+
+    #[test]
+    fn test_run_with_timeout() {
+        let mut cmd = Command::new("echo");
+        cmd.arg("done");
+        let output = run_with_timeout(cmd, Duration::from_secs(5)).unwrap();
+        assert_eq!(output, b"done\n");
+
+        let cmd = Command::new("false");
+        run_with_timeout(cmd, Duration::from_secs(5)).unwrap_err();
+    }
+
+    #[test]
+    fn test_unavailable() {
+        let status = Status::with_command("/nonexistent/rpm-ostree", Duration::from_secs(5));
+        let err = status.booted().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RpmOstreeUnavailable>().unwrap().program,
+            "/nonexistent/rpm-ostree"
+        );
+
+        // Other failures are not reported as a missing binary.
+        let status = Status::with_command("false", Duration::from_secs(5));
+        let err = status.booted().unwrap_err();
+        assert!(err.downcast_ref::<RpmOstreeUnavailable>().is_none());
+    }
+
+    #[test]
+    fn test_run_timed_out() {
+        let mut cmd = Command::new("sleep");
+        cmd.arg("10");
+        let start = Instant::now();
+        let err = run_with_timeout(cmd, Duration::from_millis(200)).unwrap_err();
+
+        assert!(
+            err.to_string().contains("timed out"),
+            "unexpected error: {}",
+            err
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_stream_from_refspec() {
+        let tests = vec![
+            ("fedora:fedora/x86_64/coreos/stable", Some("stable")),
+            ("fedora:fedora/x86_64/coreos/testing", Some("testing")),
+            ("fedora:fedora/aarch64/coreos/next", Some("next")),
+            ("fedora/x86_64/coreos/stable", Some("stable")),
+            (
+                "fedora:fedora/x86_64/coreos/testing-devel",
+                Some("fedora:fedora/x86_64/coreos/testing-devel"),
+            ),
+            (
+                "custom:myorg/x86_64/coreos/stable",
+                Some("custom:myorg/x86_64/coreos/stable"),
+            ),
+            (
+                "4b8d4e5a0f7e8a1a5f2c3e1f0b6b4a8e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a",
+                None,
+            ),
+            (
+                "fedora:4b8d4e5a0f7e8a1a5f2c3e1f0b6b4a8e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a",
+                None,
+            ),
+        ];
+        for (refspec, expected) in tests {
+            assert_eq!(
+                stream_from_refspec(refspec),
+                expected.map(String::from),
+                "failed testcase: '{}'",
+                refspec
+            );
+        }
+    }
+
+    #[test]
+    fn test_pinned_checksum_stream() {
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status-checksum-origin.json");
+        let status = Status::with_runner(runner);
+
+        assert_eq!(status.booted().unwrap().stream(), None);
+    }
+
+    #[test]
+    fn test_pinned_without_version() {
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status-pinned.json");
+        let status = Status::with_runner(runner);
+
+        let booted = status.booted().unwrap();
+        assert_eq!(booted.version, None);
+        assert_eq!(
+            booted.checksum,
+            "4b8d4e5a0f7e8a1a5f2c3e1f0b6b4a8e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a"
+        );
+        assert_eq!(booted.stream(), None);
+    }
+
+    #[test]
+    fn test_schema_versions() {
+        let tests = vec![
+            "tests/fixtures/rpm-ostree-status-old.json",
+            "tests/fixtures/rpm-ostree-status.json",
+            "tests/fixtures/rpm-ostree-status-new.json",
+        ];
+        for fixture in tests {
+            let (runner, _) = mock_runner(fixture);
+            let status = Status::with_runner(runner);
+
+            let booted = status.booted().unwrap();
+            assert_eq!(
+                booted.version.as_deref(),
+                Some("30.20190923.dev.2-2"),
+                "failed testcase: {}",
+                fixture
+            );
+            assert!(status.staged().unwrap().is_none());
+            assert!(status.layered_packages().unwrap().is_empty());
+        }
+
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status-new.json");
+        let status = Status::with_runner(runner);
+        assert_eq!(status.booted().unwrap().origin, None);
+        assert_eq!(status.booted().unwrap().stream(), None);
+    }
+
+    #[test]
+    fn test_missing_field() {
+        let tests = vec![
+            (
+                r#"{"deployments": [{"booted": true, "version": "30.1"}]}"#,
+                "checksum",
+            ),
+            (r#"{"transaction": null}"#, "deployments"),
+        ];
+        for (output, field) in tests {
+            let status = Status::with_runner(move || Ok(output.as_bytes().to_vec()));
+            let err = status.booted().unwrap_err();
+            let missing = err
+                .downcast_ref::<MissingFieldError>()
+                .unwrap_or_else(|| panic!("unexpected error: {}", err));
+            assert_eq!(missing.field, field);
+        }
+    }
+
+    #[test]
+    fn test_layered_packages() {
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status-layered.json");
+        let status = Status::with_runner(runner);
+        assert_eq!(
+            status.layered_packages().unwrap(),
+            vec!["htop".to_string(), "vim-enhanced".to_string()]
+        );
+
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status.json");
+        let status = Status::with_runner(runner);
+        assert!(status.layered_packages().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_has_layered_packages() {
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status-layered.json");
+        let status = Status::with_runner(runner);
+        assert!(status.has_layered_packages().unwrap());
+
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status.json");
+        let status = Status::with_runner(runner);
+        assert!(!status.has_layered_packages().unwrap());
+    }
+
+    #[test]
+    fn test_deployments() {
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status-staged.json");
+        let status = Status::with_runner(runner);
+
+        let deployments = status.deployments().unwrap();
+        assert_eq!(deployments.len(), 2);
+        assert!(deployments[0].staged);
+        assert!(!deployments[0].booted);
+        assert!(!deployments[1].staged);
+        assert!(deployments[1].booted);
+
+        let staged = status.staged().unwrap().unwrap();
+        assert_eq!(staged.version.as_deref(), Some("30.20191002.0"));
+        assert_eq!(
+            status.booted().unwrap().version.as_deref(),
+            Some("30.20190923.dev.2-2")
+        );
+    }
+
+    #[test]
+    fn test_no_staged() {
+        let (runner, _) = mock_runner("tests/fixtures/rpm-ostree-status.json");
+        let status = Status::with_runner(runner);
+
+        assert!(status.staged().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_status_cached() {
+        let (runner, calls) = mock_runner("tests/fixtures/rpm-ostree-status.json");
+        let status = Status::with_runner(runner);
+        assert_eq!(calls.get(), 0);
+
+        status.booted().unwrap();
+        status.booted().unwrap();
+        status.staged().unwrap();
+        status.deployments().unwrap();
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_failure_cached() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        let status = Status::with_runner(move || {
+            counter.set(counter.get() + 1);
+            bail!("rpm-ostree timed out")
+        });
+
+        let err = status.booted().unwrap_err();
+        assert_eq!(err.to_string(), "rpm-ostree timed out");
+        status.staged().unwrap_err();
+        status.deployments().unwrap_err();
+        status.layered_packages().unwrap_err();
+        let err = status.has_layered_packages().unwrap_err();
+        assert_eq!(err.to_string(), "rpm-ostree timed out");
+        assert_eq!(calls.get(), 1);
+    }
+}
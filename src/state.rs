@@ -0,0 +1,198 @@
+//! Persistent state of the last successful report and of the report sequence.
+
+use crate::identity::Identity;
+use failure::{bail, Fallible, ResultExt};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default path of the state file.
+pub(crate) const DEFAULT_STATE_FILE: &str = "/var/lib/fedora-coreos-pinger/state.json";
+/// Default path of the report sequence counter.
+pub(crate) const DEFAULT_SEQUENCE_FILE: &str = "/var/lib/fedora-coreos-pinger/seq";
+
+/// Record of the last successful report.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct State {
+    /// OS version of the booted deployment when reported, if known.
+    pub(crate) current_os_version: Option<String>,
+    /// Time of the report, in seconds since the Unix epoch.
+    pub(crate) reported_at: u64,
+}
+
+impl State {
+    /// Read the state from the given file.
+    pub(crate) fn read(path: &Path) -> Fallible<Self> {
+        let contents = std::fs::read(path)
+            .context(format!("failed to read state file '{}'", path.display()))?;
+        let state = serde_json::from_slice(&contents)
+            .context(format!("failed to parse state file '{}'", path.display()))?;
+        Ok(state)
+    }
+
+    /// Atomically write the state to the given file.
+    pub(crate) fn write(&self, path: &Path) -> Fallible<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .context(format!("failed to create directory '{}'", dir.display()))?;
+        }
+        let contents = serde_json::to_vec(self).context("failed to serialize state")?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)
+            .context(format!("failed to write '{}'", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .context(format!("failed to rename '{}'", tmp_path.display()))?;
+        Ok(())
+    }
+}
+
+/// Whether the identity should be reported, i.e. if the OS version changed
+/// since the last report recorded in `state_path`.
+///
+/// A missing or corrupt state file means a report is due.
+pub(crate) fn should_report(id: &Identity, state_path: &Path) -> bool {
+    match State::read(state_path) {
+        Ok(state) => state.current_os_version != id.current_os_version,
+        Err(e) => {
+            if state_path.exists() {
+                warn!("{}", e);
+            } else {
+                debug!("no state file '{}'", state_path.display());
+            }
+            true
+        }
+    }
+}
+
+/// Record a successful report of the identity in `state_path`.
+pub(crate) fn record_report(id: &Identity, state_path: &Path) -> Fallible<()> {
+    let reported_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let state = State {
+        current_os_version: id.current_os_version.clone(),
+        reported_at,
+    };
+    state.write(state_path)
+}
+
+/// Return the sequence number of the report being built, and persist the
+/// incremented counter in `path`.
+///
+/// The first report is numbered 0. A missing or corrupt counter is reset to
+/// 0, but failing to read it is an error.
+pub(crate) fn next_report_sequence(path: &Path) -> Fallible<u64> {
+    let sequence: u64 = match std::fs::read_to_string(path) {
+        Ok(contents) => contents.trim().parse().unwrap_or_else(|e| {
+            warn!(
+                "corrupt report sequence file '{}', resetting: {}",
+                path.display(),
+                e
+            );
+            0
+        }),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            debug!("no report sequence file '{}'", path.display());
+            0
+        }
+        Err(e) => bail!(
+            "failed to read report sequence file '{}': {}",
+            path.display(),
+            e
+        ),
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .context(format!("failed to create directory '{}'", dir.display()))?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, format!("{}\n", sequence.saturating_add(1)))
+        .context(format!("failed to write '{}'", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .context(format!("failed to rename '{}'", tmp_path.display()))?;
+
+    Ok(sequence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unchanged_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let id = Identity::mock_default("minimal");
+
+        assert!(should_report(&id, &path));
+        record_report(&id, &path).unwrap();
+        assert!(!should_report(&id, &path));
+
+        let state = State::read(&path).unwrap();
+        assert_eq!(state.current_os_version, id.current_os_version);
+        assert!(state.reported_at > 0);
+    }
+
+    #[test]
+    fn test_changed_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        record_report(&Identity::mock_default("minimal"), &path).unwrap();
+
+        let id = Identity {
+            current_os_version: Some("31.20191217.2.0".to_string()),
+            ..Identity::mock_default("minimal")
+        };
+        assert!(should_report(&id, &path));
+    }
+
+    #[test]
+    fn test_corrupt_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        std::fs::write(&path, "{\"current_os_version\":").unwrap();
+
+        let id = Identity::mock_default("minimal");
+        assert!(should_report(&id, &path));
+
+        // A corrupt state is replaced on the next successful report.
+        record_report(&id, &path).unwrap();
+        assert!(!should_report(&id, &path));
+    }
+
+    #[test]
+    fn test_report_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pinger/seq");
+
+        assert_eq!(next_report_sequence(&path).unwrap(), 0);
+        assert_eq!(next_report_sequence(&path).unwrap(), 1);
+        assert_eq!(next_report_sequence(&path).unwrap(), 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "3\n");
+    }
+
+    #[test]
+    fn test_corrupt_report_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seq");
+        std::fs::write(&path, "not a number").unwrap();
+
+        assert_eq!(next_report_sequence(&path).unwrap(), 0);
+        assert_eq!(next_report_sequence(&path).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_unreadable_report_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seq");
+        std::fs::create_dir(&path).unwrap();
+
+        // Only a missing counter is reset, not one that cannot be read.
+        next_report_sequence(&path).unwrap_err();
+        assert!(path.is_dir());
+    }
+}